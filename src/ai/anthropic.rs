@@ -1,8 +1,12 @@
+use super::retry::RetryPolicy;
+use super::stream::{is_event_stream, read_sse, TokenEcho, REQUEST_TIMEOUT};
 use super::{
-    build_changelog_prompt, build_prompt, ChangelogContext, ChangelogSummary, CommitContext,
-    CommitMessage,
+    build_changelog_prompt, build_prompt, check_status, parse_json_content, AiProvider,
+    ChangelogContext, ChangelogSummary, CommitContext, CommitMessage, CommitPlan,
+    CHANGELOG_SYSTEM_PROMPT, COMMIT_SYSTEM_PROMPT, SPLIT_SYSTEM_PROMPT,
 };
 use anyhow::{Context, Result};
+use async_trait::async_trait;
 use colored::*;
 use serde::{Deserialize, Serialize};
 use std::time::Duration;
@@ -12,7 +16,9 @@ pub struct AnthropicClient {
     model: String,
     base_url: String,
     client: reqwest::Client,
+    retry: RetryPolicy,
     initial_max_tokens: u32,
+    stream: bool,
 }
 
 impl AnthropicClient {
@@ -23,7 +29,6 @@ impl AnthropicClient {
         initial_max_tokens: u32,
     ) -> Self {
         let client = reqwest::Client::builder()
-            .timeout(Duration::from_secs(30))
             .connect_timeout(Duration::from_secs(10))
             .build()
             .expect("Failed to create HTTP client");
@@ -33,21 +38,36 @@ impl AnthropicClient {
             model,
             base_url: base_url.unwrap_or_else(|| "https://api.anthropic.com".to_string()),
             client,
+            retry: RetryPolicy::default(),
             initial_max_tokens,
+            stream: false,
         }
     }
 
-    pub async fn generate_commit_message(
+    pub fn with_retry(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    /// Request `stream: true` for commit generation and echo tokens live.
+    pub fn with_streaming(mut self, stream: bool) -> Self {
+        self.stream = stream;
+        self
+    }
+
+    /// Send one request and return the text of the reply. With `stream`,
+    /// text deltas are echoed as they arrive.
+    async fn send_message(
         &self,
-        diff: &str,
-        context: &CommitContext,
+        system: &str,
+        prompt: String,
+        stream: bool,
         debug: bool,
-    ) -> Result<CommitMessage> {
-        let prompt = build_prompt(diff, context);
-
+    ) -> Result<String> {
         let request = AnthropicRequest {
             model: self.model.clone(),
             max_tokens: self.initial_max_tokens,
+            system: system.to_string(),
             messages: vec![AnthropicMessage {
                 role: "user".to_string(),
                 content: format!(
@@ -55,59 +75,55 @@ impl AnthropicClient {
                     prompt
                 ),
             }],
+            stream,
         };
 
         let response = self
-            .client
-            .post(format!("{}/v1/messages", self.base_url))
-            .header("x-api-key", &self.api_key)
-            .header("anthropic-version", "2023-06-01")
-            .header("content-type", "application/json")
-            .json(&request)
-            .send()
+            .retry
+            .send(
+                || {
+                    let builder = self
+                        .client
+                        .post(format!("{}/v1/messages", self.base_url))
+                        .header("x-api-key", &self.api_key)
+                        .header("anthropic-version", "2023-06-01")
+                        .header("content-type", "application/json")
+                        .json(&request);
+                    match stream {
+                        true => builder,
+                        false => builder.timeout(REQUEST_TIMEOUT),
+                    }
+                },
+                debug,
+            )
             .await
             .context("Failed to send request to Anthropic")?;
+        let response = check_status(response, "Anthropic", debug).await?;
 
-        if !response.status().is_success() {
-            let status = response.status();
-            let error_text = response.text().await?;
-
-            // Sanitize error message to avoid exposing sensitive details
-            let safe_error = match status.as_u16() {
-                401 => "Authentication failed. Please check your API key.",
-                403 => "Access forbidden. Please check your API permissions.",
-                429 => "Rate limit exceeded. Please try again later.",
-                500..=599 => "Anthropic service error. Please try again later.",
-                _ => "Request failed. Please check your configuration.",
-            };
+        let content = if stream && is_event_stream(&response) {
+            self.read_stream(response, debug).await?
+        } else {
+            let response_text = response
+                .text()
+                .await
+                .context("Failed to read response text")?;
 
             if debug {
-                eprintln!("Debug: Full error response: {}", error_text);
+                println!("\n{}", "=== DEBUG: Raw HTTP Response ===".cyan().bold());
+                println!("{}", response_text);
+                println!("{}", "=================================\n".cyan().bold());
             }
 
-            anyhow::bail!("{} (Status: {})", safe_error, status);
-        }
-
-        let response_text = response
-            .text()
-            .await
-            .context("Failed to read response text")?;
+            let api_response: AnthropicResponse = serde_json::from_str(&response_text)
+                .context("Failed to parse Anthropic response")?;
 
-        if debug {
-            println!("\n{}", "=== DEBUG: Raw HTTP Response ===".cyan().bold());
-            println!("{}", response_text);
-            println!("{}", "=================================\n".cyan().bold());
-        }
-
-        let api_response: AnthropicResponse =
-            serde_json::from_str(&response_text).context("Failed to parse Anthropic response")?;
-
-        let content = api_response
-            .content
-            .first()
-            .ok_or_else(|| anyhow::anyhow!("No response from Anthropic"))?
-            .text
-            .clone();
+            api_response
+                .content
+                .first()
+                .ok_or_else(|| anyhow::anyhow!("No response from Anthropic"))?
+                .text
+                .clone()
+        };
 
         if debug {
             println!("\n{}", "=== DEBUG: AI Message Content ===".cyan().bold());
@@ -115,197 +131,110 @@ impl AnthropicClient {
             println!("{}", "==================================\n".cyan().bold());
         }
 
-        // Strip markdown code block wrapper if present
-        let clean_content = if content.starts_with("```json") && content.ends_with("```") {
-            content
-                .strip_prefix("```json")
-                .and_then(|s| s.strip_suffix("```"))
-                .map(|s| s.trim())
-                .unwrap_or(&content)
-        } else if content.starts_with("```") && content.ends_with("```") {
-            content
-                .strip_prefix("```")
-                .and_then(|s| s.strip_suffix("```"))
-                .map(|s| s.trim())
-                .unwrap_or(&content)
-        } else {
-            &content
-        };
+        Ok(content)
+    }
 
-        // Try to parse the content directly first
-        let commit_message = match serde_json::from_str::<CommitMessage>(clean_content) {
-            Ok(msg) => msg,
-            Err(_) => {
-                // If direct parsing fails, try to extract JSON object
-                // This is more robust than simple string searching
-                let mut depth = 0;
-                let mut start_idx = None;
-                let mut end_idx = None;
-
-                for (idx, ch) in clean_content.char_indices() {
-                    match ch {
-                        '{' => {
-                            if depth == 0 && start_idx.is_none() {
-                                start_idx = Some(idx);
-                            }
-                            depth += 1;
-                        }
-                        '}' => {
-                            depth -= 1;
-                            if depth == 0 && start_idx.is_some() {
-                                end_idx = Some(idx + ch.len_utf8());
-                                break;
-                            }
-                        }
-                        _ => {}
-                    }
+    /// Consume a `stream: true` response, echoing text deltas as they arrive.
+    async fn read_stream(&self, response: reqwest::Response, debug: bool) -> Result<String> {
+        let mut content = String::new();
+        let mut stop_reason = None;
+        let mut stream_error = None;
+        let mut echo = TokenEcho::new();
+
+        let result = read_sse(response, |data| {
+            match serde_json::from_str::<StreamEvent>(data) {
+                Ok(StreamEvent::ContentBlockDelta { delta }) => {
+                    echo.push(&delta.text);
+                    content.push_str(&delta.text);
                 }
-
-                if let (Some(start), Some(end)) = (start_idx, end_idx) {
-                    let json_str = &clean_content[start..end];
-                    serde_json::from_str(json_str)
-                        .context("Failed to parse extracted JSON from Anthropic response")?
-                } else {
-                    anyhow::bail!("No valid JSON object found in Anthropic response");
+                Ok(StreamEvent::MessageDelta { delta }) => {
+                    stop_reason = delta.stop_reason;
                 }
+                Ok(StreamEvent::Error { error }) => {
+                    stream_error = Some(error.message);
+                }
+                Ok(StreamEvent::Other) => {}
+                Err(_) if debug => {
+                    eprintln!("Debug: Skipping unrecognized stream event: {}", data);
+                }
+                Err(_) => {}
             }
-        };
-
-        Ok(commit_message)
-    }
-
-    pub async fn generate_changelog(
-        &self,
-        commits: &[crate::git::CommitInfo],
-        context: &ChangelogContext,
-        debug: bool,
-    ) -> Result<ChangelogSummary> {
-        let prompt = build_changelog_prompt(commits, context);
-
-        let request = AnthropicRequest {
-            model: self.model.clone(),
-            max_tokens: self.initial_max_tokens,
-            messages: vec![AnthropicMessage {
-                role: "user".to_string(),
-                content: format!(
-                    "{}\n\nPlease respond with only the JSON object, no other text.",
-                    prompt
-                ),
-            }],
-        };
-
-        let response = self
-            .client
-            .post(format!("{}/v1/messages", self.base_url))
-            .header("x-api-key", &self.api_key)
-            .header("anthropic-version", "2023-06-01")
-            .header("content-type", "application/json")
-            .json(&request)
-            .send()
-            .await
-            .context("Failed to send request to Anthropic")?;
-
-        if !response.status().is_success() {
-            let status = response.status();
-            let error_text = response.text().await?;
-
-            let safe_error = match status.as_u16() {
-                401 => "Authentication failed. Please check your API key.",
-                403 => "Access forbidden. Please check your API permissions.",
-                429 => "Rate limit exceeded. Please try again later.",
-                500..=599 => "Anthropic service error. Please try again later.",
-                _ => "Request failed. Please check your configuration.",
-            };
+            Ok(())
+        })
+        .await;
+        echo.finish();
+        result?;
 
+        if let Some(message) = stream_error {
             if debug {
-                eprintln!("Debug: Full error response: {}", error_text);
+                eprintln!("Debug: Stream error: {}", message);
             }
-
-            anyhow::bail!("{} (Status: {})", safe_error, status);
+            anyhow::bail!("Anthropic returned an error while streaming the response.");
         }
 
-        let response_text = response
-            .text()
-            .await
-            .context("Failed to read response text")?;
-
-        if debug {
-            println!("\n{}", "=== DEBUG: Raw HTTP Response ===".cyan().bold());
-            println!("{}", response_text);
-            println!("{}", "=================================\n".cyan().bold());
-        }
-
-        let api_response: AnthropicResponse =
-            serde_json::from_str(&response_text).context("Failed to parse Anthropic response")?;
-
-        let content = api_response
-            .content
-            .first()
-            .ok_or_else(|| anyhow::anyhow!("No response from Anthropic"))?
-            .text
-            .clone();
-
-        if debug {
-            println!("\n{}", "=== DEBUG: AI Message Content ===".cyan().bold());
-            println!("{}", content);
-            println!("{}", "==================================\n".cyan().bold());
+        if stop_reason.as_deref() == Some("max_tokens") {
+            anyhow::bail!("AI response was truncated before completing the JSON (stop_reason=max_tokens). Try increasing max_tokens or reducing the diff size.");
         }
 
-        // Strip markdown code block wrapper if present
-        let clean_content = if content.starts_with("```json") && content.ends_with("```") {
-            content
-                .strip_prefix("```json")
-                .and_then(|s| s.strip_suffix("```"))
-                .map(|s| s.trim())
-                .unwrap_or(&content)
-        } else if content.starts_with("```") && content.ends_with("```") {
-            content
-                .strip_prefix("```")
-                .and_then(|s| s.strip_suffix("```"))
-                .map(|s| s.trim())
-                .unwrap_or(&content)
-        } else {
-            &content
-        };
+        Ok(content)
+    }
+}
 
-        let changelog = match serde_json::from_str::<ChangelogSummary>(clean_content) {
-            Ok(summary) => summary,
-            Err(_) => {
-                // Try to extract JSON object
-                let mut depth = 0;
-                let mut start_idx = None;
-                let mut end_idx = None;
-
-                for (idx, ch) in clean_content.char_indices() {
-                    match ch {
-                        '{' => {
-                            if depth == 0 && start_idx.is_none() {
-                                start_idx = Some(idx);
-                            }
-                            depth += 1;
-                        }
-                        '}' => {
-                            depth -= 1;
-                            if depth == 0 && start_idx.is_some() {
-                                end_idx = Some(idx + ch.len_utf8());
-                                break;
-                            }
-                        }
-                        _ => {}
-                    }
-                }
+#[async_trait]
+impl AiProvider for AnthropicClient {
+    async fn generate_commit_message(
+        &self,
+        diff: &str,
+        context: &CommitContext,
+        debug: bool,
+    ) -> Result<CommitMessage> {
+        let stream = self.stream && context.stage.streams();
+        let content = self
+            .send_message(
+                COMMIT_SYSTEM_PROMPT,
+                build_prompt(diff, context),
+                stream,
+                debug,
+            )
+            .await?;
+
+        parse_json_content(&content, "Anthropic")
+    }
 
-                if let (Some(start), Some(end)) = (start_idx, end_idx) {
-                    let json_str = &clean_content[start..end];
-                    serde_json::from_str(json_str)
-                        .context("Failed to parse extracted JSON from Anthropic response")?
-                } else {
-                    anyhow::bail!("No valid JSON object found in Anthropic response");
-                }
-            }
-        };
+    async fn generate_changelog(
+        &self,
+        commits: &[crate::git::CommitInfo],
+        context: &ChangelogContext,
+        debug: bool,
+    ) -> Result<ChangelogSummary> {
+        let content = self
+            .send_message(
+                CHANGELOG_SYSTEM_PROMPT,
+                build_changelog_prompt(commits, context),
+                false,
+                debug,
+            )
+            .await?;
+
+        parse_json_content(&content, "Anthropic")
+    }
 
-        Ok(changelog)
+    async fn generate_commit_plan(
+        &self,
+        changes: &str,
+        context: &CommitContext,
+        debug: bool,
+    ) -> Result<CommitPlan> {
+        let content = self
+            .send_message(
+                SPLIT_SYSTEM_PROMPT,
+                build_prompt(changes, context),
+                false,
+                debug,
+            )
+            .await?;
+
+        parse_json_content(&content, "Anthropic")
     }
 }
 
@@ -313,7 +242,10 @@ impl AnthropicClient {
 struct AnthropicRequest {
     model: String,
     max_tokens: u32,
+    system: String,
     messages: Vec<AnthropicMessage>,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    stream: bool,
 }
 
 #[derive(Serialize)]
@@ -331,3 +263,84 @@ struct AnthropicResponse {
 struct Content {
     text: String,
 }
+
+/// Server-sent events emitted by the Messages API when `stream: true`
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum StreamEvent {
+    ContentBlockDelta {
+        delta: TextDelta,
+    },
+    MessageDelta {
+        delta: MessageDeltaBody,
+    },
+    Error {
+        error: StreamError,
+    },
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Deserialize)]
+struct TextDelta {
+    #[serde(default)]
+    text: String,
+}
+
+#[derive(Deserialize)]
+struct MessageDeltaBody {
+    stop_reason: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct StreamError {
+    message: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_streaming_request_collects_text_deltas() {
+        let mut server = mockito::Server::new_async().await;
+        let body = [
+            "event: message_start\ndata: {\"type\":\"message_start\",\"message\":{}}",
+            r#"event: content_block_delta
+data: {"type":"content_block_delta","index":0,"delta":{"type":"text_delta","text":"{\"type\":\"perf\",\"scope\":\"git\","}}"#,
+            r#"event: content_block_delta
+data: {"type":"content_block_delta","index":0,"delta":{"type":"text_delta","text":"\"description\":\"优化\",\"description_en\":\"Speed up\",\"breaking_change\":null}"}}"#,
+            r#"event: message_delta
+data: {"type":"message_delta","delta":{"stop_reason":"end_turn"}}"#,
+            "event: message_stop\ndata: {\"type\":\"message_stop\"}",
+        ]
+        .join("\n\n");
+        let mock = server
+            .mock("POST", "/v1/messages")
+            .match_body(mockito::Matcher::PartialJson(
+                serde_json::json!({"stream": true}),
+            ))
+            .with_status(200)
+            .with_header("content-type", "text/event-stream")
+            .with_body(body)
+            .create_async()
+            .await;
+
+        let client = AnthropicClient::new(
+            "key".to_string(),
+            "claude-sonnet".to_string(),
+            Some(server.url()),
+            500,
+        )
+        .with_streaming(true);
+        let context = CommitContext::for_test();
+        let message = client
+            .generate_commit_message("-a\n+b", &context, false)
+            .await
+            .unwrap();
+
+        mock.assert_async().await;
+        assert_eq!(message.commit_type, "perf");
+        assert_eq!(message.scope.as_deref(), Some("git"));
+    }
+}
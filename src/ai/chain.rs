@@ -0,0 +1,270 @@
+use super::{
+    AiProvider, ChangelogContext, ChangelogSummary, CommitContext, CommitMessage, CommitPlan,
+    PromptStage,
+};
+use anyhow::Result;
+use colored::*;
+use std::future::Future;
+
+/// Ordered list of providers; each one is tried in turn until one succeeds.
+#[derive(Default)]
+pub struct ProviderChain {
+    entries: Vec<ChainEntry>,
+}
+
+struct ChainEntry {
+    label: String,
+    client: Box<dyn AiProvider>,
+}
+
+impl ProviderChain {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Append a provider. `label` is shown to the user, e.g. "openai (gpt-4.1)".
+    pub fn push(&mut self, label: String, client: Box<dyn AiProvider>) {
+        self.entries.push(ChainEntry { label, client });
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Generate a commit message, returning it with the label of the provider
+    /// that produced it.
+    pub async fn generate_commit_message(
+        &self,
+        diff: &str,
+        context: &CommitContext,
+        debug: bool,
+    ) -> Result<(CommitMessage, &str)> {
+        self.first_ok(|client| client.generate_commit_message(diff, context, debug))
+            .await
+    }
+
+    /// Generate `count` alternative commit messages concurrently. Variants
+    /// that fail are dropped; this only fails when none was generated.
+    pub async fn generate_commit_messages(
+        &self,
+        diff: &str,
+        context: &CommitContext,
+        count: usize,
+        debug: bool,
+    ) -> Result<Vec<(CommitMessage, &str)>> {
+        let results = futures::future::join_all((1..=count).map(|index| {
+            let context = CommitContext {
+                stage: PromptStage::Variant {
+                    index,
+                    total: count,
+                },
+                ..context.clone()
+            };
+            async move { self.generate_commit_message(diff, &context, debug).await }
+        }))
+        .await;
+
+        let mut messages = Vec::new();
+        let mut last_error = None;
+        for result in results {
+            match result {
+                Ok(message) => messages.push(message),
+                Err(e) => last_error = Some(e),
+            }
+        }
+        match last_error {
+            Some(e) if messages.is_empty() => Err(e),
+            _ => Ok(messages),
+        }
+    }
+
+    /// Generate a changelog summary, returning it with the label of the
+    /// provider that produced it.
+    pub async fn generate_changelog(
+        &self,
+        commits: &[crate::git::CommitInfo],
+        context: &ChangelogContext,
+        debug: bool,
+    ) -> Result<(ChangelogSummary, &str)> {
+        self.first_ok(|client| client.generate_changelog(commits, context, debug))
+            .await
+    }
+
+    /// Group labeled changes into commits, returning the plan with the label
+    /// of the provider that produced it.
+    pub async fn generate_commit_plan(
+        &self,
+        changes: &str,
+        context: &CommitContext,
+        debug: bool,
+    ) -> Result<(CommitPlan, &str)> {
+        self.first_ok(|client| client.generate_commit_plan(changes, context, debug))
+            .await
+    }
+
+    /// Run `request` on each provider in turn and return the first success
+    /// with the provider's label, or every failure if none succeeded.
+    async fn first_ok<'a, T, F, Fut>(&'a self, request: F) -> Result<(T, &'a str)>
+    where
+        F: Fn(&'a dyn AiProvider) -> Fut,
+        Fut: Future<Output = Result<T>>,
+    {
+        let mut failures = Vec::new();
+
+        for (idx, entry) in self.entries.iter().enumerate() {
+            match request(entry.client.as_ref()).await {
+                Ok(result) => return Ok((result, entry.label.as_str())),
+                Err(e) => {
+                    self.report_failure(idx, &e);
+                    failures.push((entry.label.as_str(), e));
+                }
+            }
+        }
+
+        Err(all_failed(failures))
+    }
+
+    fn report_failure(&self, idx: usize, error: &anyhow::Error) {
+        if let Some(next) = self.entries.get(idx + 1) {
+            eprintln!(
+                "{} {} failed: {:#}",
+                "⚠".yellow(),
+                self.entries[idx].label,
+                error
+            );
+            eprintln!("{} Falling back to {}...", "ℹ".blue(), next.label);
+        }
+    }
+}
+
+fn all_failed(mut failures: Vec<(&str, anyhow::Error)>) -> anyhow::Error {
+    if failures.len() <= 1 {
+        // Keep the original error untouched when there is no fallback
+        return failures
+            .pop()
+            .map(|(_, e)| e)
+            .unwrap_or_else(|| anyhow::anyhow!("No AI provider configured"));
+    }
+
+    let details: Vec<String> = failures
+        .iter()
+        .map(|(label, e)| format!("{}: {:#}", label, e))
+        .collect();
+    anyhow::anyhow!("All AI providers failed:\n  {}", details.join("\n  "))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_trait::async_trait;
+
+    struct StubProvider {
+        fail: bool,
+    }
+
+    #[async_trait]
+    impl AiProvider for StubProvider {
+        async fn generate_commit_message(
+            &self,
+            _diff: &str,
+            _context: &CommitContext,
+            _debug: bool,
+        ) -> Result<CommitMessage> {
+            if self.fail {
+                anyhow::bail!("service unavailable");
+            }
+            Ok(CommitMessage {
+                commit_type: "fix".to_string(),
+                scope: None,
+                description: "修复".to_string(),
+                description_en: "Fix".to_string(),
+                ..Default::default()
+            })
+        }
+
+        async fn generate_changelog(
+            &self,
+            _commits: &[crate::git::CommitInfo],
+            _context: &ChangelogContext,
+            _debug: bool,
+        ) -> Result<ChangelogSummary> {
+            anyhow::bail!("service unavailable")
+        }
+    }
+
+    /// Fails for the second variant only.
+    struct FlakyVariants;
+
+    #[async_trait]
+    impl AiProvider for FlakyVariants {
+        async fn generate_commit_message(
+            &self,
+            _diff: &str,
+            context: &CommitContext,
+            _debug: bool,
+        ) -> Result<CommitMessage> {
+            match context.stage {
+                PromptStage::Variant { index: 2, .. } => anyhow::bail!("timeout"),
+                PromptStage::Variant { index, .. } => Ok(CommitMessage {
+                    description: format!("variant {}", index),
+                    ..Default::default()
+                }),
+                _ => anyhow::bail!("expected a variant"),
+            }
+        }
+
+        async fn generate_changelog(
+            &self,
+            _commits: &[crate::git::CommitInfo],
+            _context: &ChangelogContext,
+            _debug: bool,
+        ) -> Result<ChangelogSummary> {
+            anyhow::bail!("service unavailable")
+        }
+    }
+
+    #[tokio::test]
+    async fn test_falls_back_to_next_provider() {
+        let mut chain = ProviderChain::new();
+        chain.push("primary".to_string(), Box::new(StubProvider { fail: true }));
+        chain.push("backup".to_string(), Box::new(StubProvider { fail: false }));
+
+        let (message, label) = chain
+            .generate_commit_message("", &CommitContext::for_test(), false)
+            .await
+            .unwrap();
+        assert_eq!(label, "backup");
+        assert_eq!(message.commit_type, "fix");
+    }
+
+    #[tokio::test]
+    async fn test_reports_every_failure() {
+        let mut chain = ProviderChain::new();
+        chain.push("primary".to_string(), Box::new(StubProvider { fail: true }));
+        chain.push("backup".to_string(), Box::new(StubProvider { fail: true }));
+
+        let err = chain
+            .generate_commit_message("", &CommitContext::for_test(), false)
+            .await
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("primary: service unavailable"));
+        assert!(err.contains("backup: service unavailable"));
+    }
+
+    #[tokio::test]
+    async fn test_generates_variants_and_drops_failures() {
+        let mut chain = ProviderChain::new();
+        chain.push("flaky".to_string(), Box::new(FlakyVariants));
+
+        let messages = chain
+            .generate_commit_messages("", &CommitContext::for_test(), 3, false)
+            .await
+            .unwrap();
+        let descriptions: Vec<&str> = messages
+            .iter()
+            .map(|(m, _)| m.description.as_str())
+            .collect();
+        assert_eq!(descriptions, vec!["variant 1", "variant 3"]);
+    }
+}
@@ -0,0 +1,273 @@
+use super::retry::RetryPolicy;
+use super::{
+    build_changelog_prompt, build_prompt, check_status, parse_json_content, AiProvider,
+    ChangelogContext, ChangelogSummary, CommitContext, CommitMessage, CommitPlan,
+    CHANGELOG_SYSTEM_PROMPT, COMMIT_SYSTEM_PROMPT, SPLIT_SYSTEM_PROMPT,
+};
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use colored::*;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// Client for the Google Gemini `generateContent` API.
+pub struct GeminiClient {
+    api_key: String,
+    model: String,
+    base_url: String,
+    client: reqwest::Client,
+    retry: RetryPolicy,
+    max_tokens: u32,
+}
+
+impl GeminiClient {
+    pub fn new(api_key: String, model: String, base_url: Option<String>, max_tokens: u32) -> Self {
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(30))
+            .connect_timeout(Duration::from_secs(10))
+            .build()
+            .expect("Failed to create HTTP client");
+
+        Self {
+            api_key,
+            model,
+            base_url: base_url
+                .map(|url| url.trim_end_matches('/').to_string())
+                .unwrap_or_else(|| "https://generativelanguage.googleapis.com".to_string()),
+            client,
+            retry: RetryPolicy::default(),
+            max_tokens,
+        }
+    }
+
+    pub fn with_retry(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    async fn generate_content(&self, system: &str, prompt: String, debug: bool) -> Result<String> {
+        let request = GeminiRequest {
+            system_instruction: GeminiContent {
+                role: None,
+                parts: vec![GeminiPart {
+                    text: system.to_string(),
+                }],
+            },
+            contents: vec![GeminiContent {
+                role: Some("user".to_string()),
+                parts: vec![GeminiPart { text: prompt }],
+            }],
+            generation_config: GenerationConfig {
+                response_mime_type: "application/json".to_string(),
+                max_output_tokens: self.max_tokens,
+                temperature: 0.7,
+            },
+        };
+
+        let response = self
+            .retry
+            .send(
+                || {
+                    self.client
+                        .post(format!(
+                            "{}/v1beta/models/{}:generateContent",
+                            self.base_url, self.model
+                        ))
+                        .header("x-goog-api-key", &self.api_key)
+                        .json(&request)
+                },
+                debug,
+            )
+            .await
+            .context("Failed to send request to Gemini")?;
+        let response = check_status(response, "Gemini", debug).await?;
+
+        let response_text = response
+            .text()
+            .await
+            .context("Failed to read response text")?;
+
+        if debug {
+            println!("\n{}", "=== DEBUG: Raw HTTP Response ===".cyan().bold());
+            println!("{}", response_text);
+            println!("{}", "=================================\n".cyan().bold());
+        }
+
+        let api_response: GeminiResponse =
+            serde_json::from_str(&response_text).context("Failed to parse Gemini response")?;
+
+        let candidate = api_response
+            .candidates
+            .into_iter()
+            .next()
+            .ok_or_else(|| anyhow::anyhow!("No response from Gemini"))?;
+
+        match candidate.finish_reason.as_deref() {
+            Some("MAX_TOKENS") => {
+                anyhow::bail!("AI response was truncated before completing the JSON (finishReason=MAX_TOKENS). Try increasing max_tokens or reducing the diff size.");
+            }
+            Some("SAFETY") | Some("RECITATION") | Some("BLOCKLIST") => {
+                anyhow::bail!("The response was blocked by the provider's content filter.");
+            }
+            _ => {}
+        }
+
+        let content: String = candidate
+            .content
+            .map(|c| c.parts.into_iter().map(|p| p.text).collect())
+            .unwrap_or_default();
+
+        if content.trim().is_empty() {
+            anyhow::bail!("Response content is empty");
+        }
+
+        if debug {
+            println!("\n{}", "=== DEBUG: AI Message Content ===".cyan().bold());
+            println!("{}", content);
+            println!("{}", "==================================\n".cyan().bold());
+        }
+
+        Ok(content)
+    }
+}
+
+#[async_trait]
+impl AiProvider for GeminiClient {
+    async fn generate_commit_message(
+        &self,
+        diff: &str,
+        context: &CommitContext,
+        debug: bool,
+    ) -> Result<CommitMessage> {
+        let content = self
+            .generate_content(COMMIT_SYSTEM_PROMPT, build_prompt(diff, context), debug)
+            .await?;
+
+        parse_json_content(&content, "Gemini")
+    }
+
+    async fn generate_changelog(
+        &self,
+        commits: &[crate::git::CommitInfo],
+        context: &ChangelogContext,
+        debug: bool,
+    ) -> Result<ChangelogSummary> {
+        let content = self
+            .generate_content(
+                CHANGELOG_SYSTEM_PROMPT,
+                build_changelog_prompt(commits, context),
+                debug,
+            )
+            .await?;
+
+        parse_json_content(&content, "Gemini")
+    }
+
+    async fn generate_commit_plan(
+        &self,
+        changes: &str,
+        context: &CommitContext,
+        debug: bool,
+    ) -> Result<CommitPlan> {
+        let content = self
+            .generate_content(SPLIT_SYSTEM_PROMPT, build_prompt(changes, context), debug)
+            .await?;
+
+        parse_json_content(&content, "Gemini")
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct GeminiRequest {
+    system_instruction: GeminiContent,
+    contents: Vec<GeminiContent>,
+    generation_config: GenerationConfig,
+}
+
+#[derive(Serialize, Deserialize)]
+struct GeminiContent {
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    role: Option<String>,
+    #[serde(default)]
+    parts: Vec<GeminiPart>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct GeminiPart {
+    #[serde(default)]
+    text: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct GenerationConfig {
+    response_mime_type: String,
+    max_output_tokens: u32,
+    temperature: f32,
+}
+
+#[derive(Deserialize)]
+struct GeminiResponse {
+    #[serde(default)]
+    candidates: Vec<Candidate>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Candidate {
+    content: Option<GeminiContent>,
+    finish_reason: Option<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mockito::Matcher;
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn test_generate_commit_message_requests_json_output() {
+        let mut server = mockito::Server::new_async().await;
+        let mock = server
+            .mock("POST", "/v1beta/models/gemini-2.0-flash:generateContent")
+            .match_header("x-goog-api-key", "test-key")
+            .match_body(Matcher::PartialJson(json!({
+                "generationConfig": {"responseMimeType": "application/json"}
+            })))
+            .with_status(200)
+            .with_body(
+                json!({
+                    "candidates": [{
+                        "content": {
+                            "role": "model",
+                            "parts": [
+                                {"text": "{\"type\":\"fix\",\"scope\":null,\"description\":\"修复空指针\","},
+                                {"text": "\"description_en\":\"Fix null pointer\",\"breaking_change\":null}"}
+                            ]
+                        },
+                        "finishReason": "STOP"
+                    }]
+                })
+                .to_string(),
+            )
+            .create_async()
+            .await;
+
+        let client = GeminiClient::new(
+            "test-key".to_string(),
+            "gemini-2.0-flash".to_string(),
+            Some(server.url()),
+            500,
+        );
+        let context = CommitContext::for_test();
+        let message = client
+            .generate_commit_message("-a\n+b", &context, false)
+            .await
+            .unwrap();
+
+        mock.assert_async().await;
+        assert_eq!(message.commit_type, "fix");
+        assert_eq!(message.description_en, "Fix null pointer");
+    }
+}
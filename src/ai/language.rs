@@ -0,0 +1,103 @@
+//! Language helpers for commit messages written in one or more languages.
+//!
+//! The first configured language is the primary one and uses the plain
+//! `description`/`body` JSON keys. Every additional language gets suffixed keys,
+//! e.g. `description_en`/`body_en`, so the default `["zh", "en"]` setup keeps
+//! the original bilingual schema.
+
+/// Languages used when `commit.languages` is not configured.
+pub const DEFAULT_LANGUAGES: &[&str] = &["zh", "en"];
+
+/// Return the configured languages, falling back to the defaults when empty.
+pub fn effective_languages(languages: &[String]) -> Vec<String> {
+    if languages.is_empty() {
+        DEFAULT_LANGUAGES.iter().map(|s| s.to_string()).collect()
+    } else {
+        languages.iter().map(|s| s.trim().to_lowercase()).collect()
+    }
+}
+
+/// JSON key for `base` ("description" or "body") in the language at `index`.
+pub fn field_key(base: &str, index: usize, language: &str) -> String {
+    if index == 0 {
+        base.to_string()
+    } else {
+        format!("{}_{}", base, language)
+    }
+}
+
+/// Human readable name used in prompts.
+pub fn display_name(language: &str) -> String {
+    match language {
+        "zh" | "zh-cn" | "zh-hans" => "Chinese".to_string(),
+        "zh-tw" | "zh-hant" => "Traditional Chinese".to_string(),
+        "en" => "English".to_string(),
+        "ja" => "Japanese".to_string(),
+        "ko" => "Korean".to_string(),
+        "de" => "German".to_string(),
+        "fr" => "French".to_string(),
+        "es" => "Spanish".to_string(),
+        "pt" => "Portuguese".to_string(),
+        "ru" => "Russian".to_string(),
+        "it" => "Italian".to_string(),
+        other => other.to_string(),
+    }
+}
+
+/// Example description and body points for the prompt's sample JSON.
+pub fn example(language: &str) -> (String, Vec<String>) {
+    let (description, body): (&str, &[&str]) = match language {
+        "zh" | "zh-cn" | "zh-hans" => (
+            "添加用户认证功能",
+            &[
+                "实现了JWT令牌验证",
+                "添加了用户登录接口",
+                "集成了OAuth2.0支持",
+            ],
+        ),
+        "en" => (
+            "Add user authentication feature",
+            &[
+                "Implement JWT token validation",
+                "Add user login endpoint",
+                "Integrate OAuth2.0 support",
+            ],
+        ),
+        "ja" => (
+            "ユーザー認証機能を追加",
+            &[
+                "JWTトークン検証を実装",
+                "ユーザーログインエンドポイントを追加",
+                "OAuth2.0サポートを統合",
+            ],
+        ),
+        other => {
+            let name = display_name(other);
+            return (
+                format!("<brief description in {}>", name),
+                vec![
+                    format!("<first point in {}>", name),
+                    format!("<second point in {}>", name),
+                ],
+            );
+        }
+    };
+
+    (
+        description.to_string(),
+        body.iter().map(|s| s.to_string()).collect(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_field_keys_keep_bilingual_schema() {
+        let languages = effective_languages(&[]);
+        assert_eq!(languages, vec!["zh", "en"]);
+        assert_eq!(field_key("description", 0, &languages[0]), "description");
+        assert_eq!(field_key("body", 1, &languages[1]), "body_en");
+    }
+}
@@ -0,0 +1,270 @@
+//! Map-reduce commit generation for diffs too large for a single prompt.
+//!
+//! The diff is split into chunks of whole files, grouped by directory. Each
+//! chunk is summarized by its own request (at most `concurrency` at a time),
+//! then a final request merges the per-chunk messages into one.
+
+use super::{language, CommitContext, CommitMessage, PromptStage, ProviderChain};
+use crate::git::parse_diff;
+use anyhow::{Context, Result};
+use futures::stream::{self, StreamExt, TryStreamExt};
+
+/// Chunk size used when `max_diff_size` is unlimited.
+pub const DEFAULT_CHUNK_SIZE: usize = 8000;
+
+#[derive(Debug, Clone, Copy)]
+pub struct MapReduceOptions {
+    /// Maximum diff bytes per chunk
+    pub chunk_size: usize,
+    /// Maximum number of chunk requests in flight
+    pub concurrency: usize,
+}
+
+/// Files summarized together by one request.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DiffChunk {
+    /// Directories or files covered, shown to the user and the model
+    pub label: String,
+    pub diff: String,
+}
+
+/// Split a diff into chunks of at most `chunk_size` bytes. Files of the same
+/// directory stay together when they fit; a single file larger than
+/// `chunk_size` becomes a chunk of its own and is budgeted by the prompt.
+pub fn split_into_chunks(diff: &str, chunk_size: usize) -> Vec<DiffChunk> {
+    // Consecutive files of the same directory form one group
+    let mut groups: Vec<(String, Vec<(String, String)>)> = Vec::new();
+    for file in parse_diff(diff) {
+        let dir = match file.path.rsplit_once('/') {
+            Some((dir, _)) => dir.to_string(),
+            None => ".".to_string(),
+        };
+        let text = file.header.clone() + &file.hunks.concat();
+        match groups.last_mut() {
+            Some((last, files)) if *last == dir => files.push((file.path, text)),
+            _ => groups.push((dir, vec![(file.path, text)])),
+        }
+    }
+
+    // Pack groups (or the files of oversized groups) into chunks
+    let mut items: Vec<(String, String)> = Vec::new();
+    for (dir, files) in groups {
+        let size: usize = files.iter().map(|(_, text)| text.len()).sum();
+        if size <= chunk_size {
+            items.push((dir, files.into_iter().map(|(_, text)| text).collect()));
+        } else {
+            items.extend(files);
+        }
+    }
+
+    let mut chunks = Vec::new();
+    let mut labels: Vec<String> = Vec::new();
+    let mut current = String::new();
+    for (label, text) in items {
+        if !current.is_empty() && current.len() + text.len() > chunk_size {
+            chunks.push(make_chunk(&mut labels, &mut current));
+        }
+        if !labels.contains(&label) {
+            labels.push(label);
+        }
+        current.push_str(&text);
+    }
+    if !current.is_empty() {
+        chunks.push(make_chunk(&mut labels, &mut current));
+    }
+    chunks
+}
+
+fn make_chunk(labels: &mut Vec<String>, diff: &mut String) -> DiffChunk {
+    let label = match labels.len() {
+        0..=3 => labels.join(", "),
+        n => format!("{} and {} more", labels[..3].join(", "), n - 3),
+    };
+    labels.clear();
+    DiffChunk {
+        label,
+        diff: std::mem::take(diff),
+    }
+}
+
+/// Steps of a map-reduce run, reported so the caller can show progress.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Progress<'a> {
+    /// The diff was split into `total` chunks
+    Started { total: usize },
+    /// Chunk `index` (1-based) was summarized
+    Summarized {
+        index: usize,
+        total: usize,
+        label: &'a str,
+    },
+}
+
+/// Generate one commit message for a huge diff by summarizing chunks first.
+/// Falls back to a single request when the diff fits in one chunk.
+pub async fn generate_commit_message<'a>(
+    chain: &'a ProviderChain,
+    diff: &str,
+    context: &CommitContext,
+    options: MapReduceOptions,
+    on_progress: impl Fn(Progress),
+    debug: bool,
+) -> Result<(CommitMessage, &'a str)> {
+    let chunks = split_into_chunks(diff, options.chunk_size);
+    if chunks.len() <= 1 {
+        return chain.generate_commit_message(diff, context, debug).await;
+    }
+
+    let total = chunks.len();
+    on_progress(Progress::Started { total });
+
+    let on_progress = &on_progress;
+    let summaries: Vec<(String, CommitMessage)> = stream::iter(chunks.into_iter().enumerate())
+        .map(|(i, chunk)| async move {
+            let chunk_context = CommitContext {
+                stage: PromptStage::Chunk {
+                    index: i + 1,
+                    total,
+                    label: chunk.label.clone(),
+                },
+                ..context.clone()
+            };
+            let (message, _) = chain
+                .generate_commit_message(&chunk.diff, &chunk_context, debug)
+                .await
+                .with_context(|| format!("Failed to summarize part {} ({})", i + 1, chunk.label))?;
+            on_progress(Progress::Summarized {
+                index: i + 1,
+                total,
+                label: &chunk.label,
+            });
+            Ok::<_, anyhow::Error>((chunk.label, message))
+        })
+        .buffered(options.concurrency.max(1))
+        .try_collect()
+        .await?;
+
+    // Only the primary language is needed to merge; translations are redone
+    let primary = language::effective_languages(&context.languages)[..1].to_vec();
+    let merge_input = summaries
+        .iter()
+        .enumerate()
+        .map(|(i, (label, message))| {
+            format!(
+                "Part {} ({}):\n{}",
+                i + 1,
+                label,
+                message.format_conventional(&primary, false)
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n\n");
+
+    let merge_context = CommitContext {
+        stage: PromptStage::Merge,
+        ..context.clone()
+    };
+    chain
+        .generate_commit_message(&merge_input, &merge_context, debug)
+        .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ai::{AiProvider, ChangelogContext, ChangelogSummary};
+    use async_trait::async_trait;
+
+    fn file_diff(path: &str, lines: usize) -> String {
+        format!(
+            "diff --git a/{0} b/{0}\n--- a/{0}\n+++ b/{0}\n@@ -1 +1,{1} @@\n{2}",
+            path,
+            lines,
+            "+line\n".repeat(lines)
+        )
+    }
+
+    #[test]
+    fn test_split_groups_files_by_directory() {
+        let diff = [
+            file_diff("src/ai/a.rs", 2),
+            file_diff("src/ai/b.rs", 2),
+            file_diff("src/git.rs", 40),
+            file_diff("README.md", 2),
+        ]
+        .concat();
+
+        let chunks = split_into_chunks(&diff, 250);
+        let labels: Vec<&str> = chunks.iter().map(|c| c.label.as_str()).collect();
+        assert_eq!(labels, vec!["src/ai", "src/git.rs", "."]);
+        assert_eq!(
+            chunks.iter().map(|c| c.diff.len()).sum::<usize>(),
+            diff.len()
+        );
+    }
+
+    /// Echoes the stage it was called for in the description.
+    struct StageEcho;
+
+    #[async_trait]
+    impl AiProvider for StageEcho {
+        async fn generate_commit_message(
+            &self,
+            diff: &str,
+            context: &CommitContext,
+            _debug: bool,
+        ) -> Result<CommitMessage> {
+            let description = match &context.stage {
+                PromptStage::Chunk { label, .. } => format!("update {}", label),
+                PromptStage::Merge => format!("merged {} parts", diff.matches("Part ").count()),
+                _ => "full".to_string(),
+            };
+            Ok(CommitMessage {
+                commit_type: "refactor".to_string(),
+                description,
+                ..Default::default()
+            })
+        }
+
+        async fn generate_changelog(
+            &self,
+            _commits: &[crate::git::CommitInfo],
+            _context: &ChangelogContext,
+            _debug: bool,
+        ) -> Result<ChangelogSummary> {
+            anyhow::bail!("not supported")
+        }
+    }
+
+    #[tokio::test]
+    async fn test_merges_chunk_summaries() {
+        let mut chain = ProviderChain::new();
+        chain.push("echo".to_string(), Box::new(StageEcho));
+        let diff = [file_diff("a/x.rs", 20), file_diff("b/y.rs", 20)].concat();
+        let context = CommitContext::default();
+        let steps = std::sync::Mutex::new(Vec::new());
+        let record = |progress: Progress| steps.lock().unwrap().push(format!("{:?}", progress));
+
+        let (message, _) =
+            generate_commit_message(&chain, &diff, &context, options(200), record, false)
+                .await
+                .unwrap();
+        assert_eq!(message.description, "merged 2 parts");
+        assert_eq!(steps.lock().unwrap().len(), 3);
+        assert_eq!(steps.lock().unwrap()[0], "Started { total: 2 }");
+
+        let (message, _) =
+            generate_commit_message(&chain, &diff, &context, options(10_000), record, false)
+                .await
+                .unwrap();
+        assert_eq!(message.description, "full");
+        assert_eq!(steps.lock().unwrap().len(), 3);
+    }
+
+    fn options(chunk_size: usize) -> MapReduceOptions {
+        MapReduceOptions {
+            chunk_size,
+            concurrency: 2,
+        }
+    }
+}
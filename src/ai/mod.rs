@@ -1,18 +1,125 @@
-use anyhow::Result;
+use crate::config::{AIConfig, GatewayConfig};
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Deserializer, Serialize};
+use std::collections::HashMap;
 
 pub mod anthropic;
+mod chain;
+pub mod gemini;
+pub mod language;
+pub mod mapreduce;
+pub mod ollama;
 pub mod openai;
+mod retry;
+pub mod split;
+mod stream;
+pub mod style;
 
-#[derive(Debug, Clone)]
+pub use chain::ProviderChain;
+pub use retry::RetryPolicy;
+pub use split::CommitPlan;
+pub use style::MessageStyle;
+
+#[derive(Debug, Clone, Default)]
 pub struct CommitContext {
     pub branch_name: Option<String>,
     pub file_count: usize,
     pub added_lines: usize,
     pub removed_lines: usize,
+    /// Languages of the commit message, primary first (empty = default zh + en)
+    pub languages: Vec<String>,
+    /// Message style the prompt should target (`commit.format`)
+    pub style: MessageStyle,
+    /// Diff budget in bytes for the prompt (`commit.max_diff_size`, 0 = unlimited)
+    pub max_diff_size: usize,
+    /// Which step of a map-reduce run this prompt is for
+    pub stage: PromptStage,
+    /// Feedback on earlier attempts, e.g. "shorter" or "mention the migration"
+    pub hints: Vec<String>,
+    /// Team rules the message must follow (see `crate::lint`)
+    pub rules: Vec<String>,
+    /// Scope that every changed file belongs to (see `crate::scopes`)
+    pub common_scope: Option<String>,
+    /// Scopes of the changed files, most changed first
+    pub changed_scopes: Vec<String>,
+    /// Every scope of the repository (empty = any scope)
+    pub known_scopes: Vec<String>,
+    /// Whether the scopes above are binding or only suggestions
+    pub enforce_scopes: bool,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+impl CommitContext {
+    /// Keep the message's scope consistent with the repository layout: the
+    /// scope shared by every changed file is always used, and scopes outside
+    /// the known ones are dropped. Suggested scopes change nothing.
+    pub fn constrain_scope(&self, message: &mut CommitMessage) {
+        if !self.enforce_scopes || self.known_scopes.is_empty() {
+            return;
+        }
+        if let Some(scope) = &self.common_scope {
+            message.scope = Some(scope.clone());
+        } else if message
+            .scope
+            .as_ref()
+            .is_some_and(|scope| !self.known_scopes.contains(scope))
+        {
+            message.scope = None;
+        }
+    }
+}
+
+#[cfg(test)]
+impl CommitContext {
+    /// Context of a small one-file change, for provider tests.
+    pub fn for_test() -> Self {
+        Self {
+            branch_name: None,
+            file_count: 1,
+            added_lines: 1,
+            removed_lines: 1,
+            ..Default::default()
+        }
+    }
+}
+
+/// Step of commit generation a prompt belongs to. Huge diffs are split into
+/// chunks that are summarized separately and then merged (see `mapreduce`);
+/// `Split` asks for several commits instead of one.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum PromptStage {
+    /// The whole diff in a single prompt
+    #[default]
+    Full,
+    /// One chunk of a larger diff
+    Chunk {
+        index: usize,
+        total: usize,
+        label: String,
+    },
+    /// Merge per-chunk summaries into one message
+    Merge,
+    /// Group labeled changes into several commits (see `split`)
+    Split,
+    /// One of several alternative messages for the same diff
+    Variant { index: usize, total: usize },
+    /// Rewrite a generated message that breaks these rules
+    Fix { violations: Vec<String> },
+}
+
+impl PromptStage {
+    /// Whether tokens may be echoed live. Chunks and variants run
+    /// concurrently, so their output would interleave.
+    pub fn streams(&self) -> bool {
+        !matches!(
+            self,
+            PromptStage::Chunk { .. } | PromptStage::Variant { .. }
+        )
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
 pub struct CommitMessage {
     #[serde(alias = "type", alias = "commit_type")]
     pub commit_type: String,
@@ -26,6 +133,9 @@ pub struct CommitMessage {
     pub body_en: Option<Vec<String>>, // 英文说明
     #[serde(deserialize_with = "deserialize_breaking_change")]
     pub breaking_change: Option<String>,
+    /// `description_<lang>` / `body_<lang>` fields for additional languages other than English
+    #[serde(flatten, default, skip_serializing_if = "HashMap::is_empty")]
+    pub translations: HashMap<String, serde_json::Value>,
 }
 
 fn deserialize_body<'de, D>(deserializer: D) -> Result<Option<Vec<String>>, D::Error>
@@ -69,46 +179,151 @@ where
 }
 
 impl CommitMessage {
-    pub fn format_conventional(&self) -> String {
-        let mut message = String::new();
+    /// Description in the language at `index` of the configured languages.
+    pub fn description_for(&self, index: usize, lang: &str) -> Option<&str> {
+        let text = match (index, lang) {
+            (0, _) => self.description.as_str(),
+            (_, "en") => self.description_en.as_str(),
+            _ => self
+                .translations
+                .get(&language::field_key("description", index, lang))
+                .and_then(|v| v.as_str())
+                .unwrap_or(""),
+        };
+        Some(text.trim()).filter(|t| !t.is_empty())
+    }
+
+    /// Body points in the language at `index` of the configured languages.
+    pub fn body_for(&self, index: usize, lang: &str) -> Vec<String> {
+        match (index, lang) {
+            (0, _) => self.body.clone().unwrap_or_default(),
+            (_, "en") => self.body_en.clone().unwrap_or_default(),
+            _ => match self
+                .translations
+                .get(&language::field_key("body", index, lang))
+            {
+                Some(serde_json::Value::String(s)) => vec![s.clone()],
+                Some(serde_json::Value::Array(items)) => items
+                    .iter()
+                    .filter_map(|v| v.as_str().map(str::to_string))
+                    .collect(),
+                _ => Vec::new(),
+            },
+        }
+    }
+
+    /// Render the message in the given style. `include_emoji` only affects
+    /// the conventional header; gitmoji always leads with an emoji.
+    pub fn render(&self, style: MessageStyle, languages: &[String], include_emoji: bool) -> String {
+        match style {
+            MessageStyle::Conventional => self.format_conventional(languages, include_emoji),
+            MessageStyle::Gitmoji => self.format_gitmoji(languages),
+            MessageStyle::Angular => self.format_angular(languages),
+            MessageStyle::Plain => self.assemble("", false, languages),
+            MessageStyle::Kernel => self.format_kernel(languages),
+        }
+    }
+
+    /// `type(scope): description`, optionally led by the type's emoji.
+    pub fn format_conventional(&self, languages: &[String], include_emoji: bool) -> String {
+        let mut prefix = self.conventional_prefix();
+        if include_emoji {
+            prefix.insert_str(0, &format!("{} ", style::emoji_for_type(&self.commit_type)));
+        }
+        self.assemble(&prefix, false, languages)
+    }
+
+    /// `<emoji> (scope): description`
+    pub fn format_gitmoji(&self, languages: &[String]) -> String {
+        let mut prefix = style::emoji_for_type(&self.commit_type).to_string();
+        prefix.push(' ');
+        if let Some(scope) = &self.scope {
+            prefix.push_str(&format!("({}): ", scope));
+        }
+        self.assemble(&prefix, false, languages)
+    }
+
+    /// Conventional header with Angular's subject rules enforced.
+    pub fn format_angular(&self, languages: &[String]) -> String {
+        self.assemble(&self.conventional_prefix(), true, languages)
+    }
 
-        // Header: type(scope): 中文描述
-        message.push_str(&self.commit_type);
+    /// `subsystem: summary`, using the scope as the subsystem.
+    pub fn format_kernel(&self, languages: &[String]) -> String {
+        let prefix = self
+            .scope
+            .as_deref()
+            .map(|scope| format!("{}: ", scope))
+            .unwrap_or_default();
+        self.assemble(&prefix, true, languages)
+    }
+
+    fn conventional_prefix(&self) -> String {
+        let mut prefix = self.commit_type.clone();
         if let Some(scope) = &self.scope {
-            message.push_str(&format!("({})", scope));
-        }
-        message.push_str(": ");
-        message.push_str(&self.description);
-        message.push('\n');
-        message.push_str(&self.description_en);
-
-        // Body - 双语格式
-        if let (Some(body_zh), Some(body_en)) = (&self.body, &self.body_en) {
-            if !body_zh.is_empty() || !body_en.is_empty() {
-                message.push_str("\n\n");
-
-                // 使用较长的数组长度，确保所有内容都被包含
-                let max_len = body_zh.len().max(body_en.len());
-
-                for i in 0..max_len {
-                    if i > 0 {
-                        message.push('\n');
-                    }
-
-                    // 安全获取中文内容
-                    if let Some(zh) = body_zh.get(i) {
-                        message.push_str(zh);
-                        message.push('\n');
-                    }
-
-                    // 安全获取英文内容
-                    if let Some(en) = body_en.get(i) {
-                        message.push_str(en);
-                    } else if body_zh.get(i).is_some() {
-                        // 如果有中文但没有对应英文，添加占位符
-                        message.push_str("[Translation needed]");
-                    }
+            prefix.push_str(&format!("({})", scope));
+        }
+        prefix.push_str(": ");
+        prefix
+    }
+
+    /// Build the full message: `prefix` plus the primary description, the
+    /// other languages' descriptions, interleaved body points and the
+    /// breaking change footer. `strict_subject` lowercases the first letter
+    /// and drops a trailing period from every description line.
+    fn assemble(&self, prefix: &str, strict_subject: bool, languages: &[String]) -> String {
+        let languages = language::effective_languages(languages);
+        let subject = |text: &str| {
+            if strict_subject {
+                strict_subject_line(text)
+            } else {
+                text.to_string()
+            }
+        };
+        let mut message = String::new();
+
+        // Header: 前缀 + 主语言描述，其余语言的描述各占一行
+        message.push_str(prefix);
+        message.push_str(&subject(
+            self.description_for(0, &languages[0]).unwrap_or(""),
+        ));
+        for (i, lang) in languages.iter().enumerate().skip(1) {
+            if let Some(description) = self.description_for(i, lang) {
+                message.push('\n');
+                message.push_str(&subject(description));
+            }
+        }
+
+        // Body - 每条说明按语言顺序依次排列
+        let bodies: Vec<Vec<String>> = languages
+            .iter()
+            .enumerate()
+            .map(|(i, lang)| self.body_for(i, lang))
+            .collect();
+
+        // 使用最长的数组长度，确保所有内容都被包含
+        let max_len = bodies.iter().map(Vec::len).max().unwrap_or(0);
+        if max_len > 0 {
+            message.push_str("\n\n");
+
+            for i in 0..max_len {
+                if i > 0 {
+                    message.push('\n');
                 }
+
+                let lines: Vec<&str> = bodies
+                    .iter()
+                    .enumerate()
+                    .filter_map(|(lang_idx, body)| match body.get(i) {
+                        Some(text) => Some(text.as_str()),
+                        // 主语言有内容但缺少对应翻译时，添加占位符
+                        None if lang_idx > 0 && bodies[0].get(i).is_some() => {
+                            Some("[Translation needed]")
+                        }
+                        None => None,
+                    })
+                    .collect();
+                message.push_str(&lines.join("\n"));
             }
         }
 
@@ -123,60 +338,473 @@ impl CommitMessage {
     }
 }
 
-pub enum AIClient {
-    OpenAI(openai::OpenAIClient),
-    Anthropic(anthropic::AnthropicClient),
+/// Lowercase an initial ASCII capital and drop a trailing period.
+fn strict_subject_line(text: &str) -> String {
+    let text = text.trim().trim_end_matches('.');
+    let mut chars = text.chars();
+    match chars.next() {
+        // Keep acronyms such as "API" or "JWT" intact
+        Some(first)
+            if first.is_ascii_uppercase()
+                && !chars.next().is_some_and(|c| c.is_ascii_uppercase()) =>
+        {
+            let mut line = first.to_ascii_lowercase().to_string();
+            line.push_str(&text[first.len_utf8()..]);
+            line
+        }
+        _ => text.to_string(),
+    }
+}
+
+/// System prompt of commit message requests.
+pub const COMMIT_SYSTEM_PROMPT: &str = "You are a helpful assistant that generates git commit messages in JSON format. Reply with exactly one valid, minified JSON object.";
+
+/// System prompt of changelog requests.
+pub const CHANGELOG_SYSTEM_PROMPT: &str = "You are a helpful assistant that generates changelog summaries in JSON format. Reply with exactly one valid, minified JSON object.";
+
+/// System prompt of `split` requests.
+pub const SPLIT_SYSTEM_PROMPT: &str = "You are a helpful assistant that splits git changes into commits and describes them in JSON format. Reply with exactly one valid, minified JSON object.";
+
+/// Turn an unsuccessful response into an error that is safe to show: the
+/// body may echo request details, so it is only printed with `debug`.
+pub(crate) async fn check_status(
+    response: reqwest::Response,
+    provider: &str,
+    debug: bool,
+) -> Result<reqwest::Response> {
+    let status = response.status();
+    if status.is_success() {
+        return Ok(response);
+    }
+
+    let error_text = response.text().await?;
+    if debug {
+        eprintln!("Debug: Full error response: {}", error_text);
+    }
+
+    let safe_error = match status.as_u16() {
+        400 => "Invalid request. Please check the model name and your configuration.".to_string(),
+        401 => "Authentication failed. Please check your API key.".to_string(),
+        403 => "Access forbidden. Please check your API permissions.".to_string(),
+        404 => "Model not found. Please check the model name.".to_string(),
+        429 => "Rate limit or quota exceeded. Please try again later.".to_string(),
+        500..=599 => format!("{} service error. Please try again later.", provider),
+        _ => "Request failed. Please check your configuration.".to_string(),
+    };
+    anyhow::bail!("{} (Status: {})", safe_error, status)
 }
 
-impl AIClient {
-    pub async fn generate_commit_message(
+/// Common interface implemented by every AI backend.
+#[async_trait]
+pub trait AiProvider: Send + Sync {
+    async fn generate_commit_message(
         &self,
         diff: &str,
         context: &CommitContext,
         debug: bool,
-    ) -> Result<CommitMessage> {
-        match self {
-            AIClient::OpenAI(client) => client.generate_commit_message(diff, context, debug).await,
-            AIClient::Anthropic(client) => {
-                client.generate_commit_message(diff, context, debug).await
-            }
-        }
-    }
+    ) -> Result<CommitMessage>;
 
-    pub async fn generate_changelog(
+    async fn generate_changelog(
         &self,
         commits: &[crate::git::CommitInfo],
         context: &ChangelogContext,
         debug: bool,
-    ) -> Result<ChangelogSummary> {
-        match self {
-            AIClient::OpenAI(client) => client.generate_changelog(commits, context, debug).await,
-            AIClient::Anthropic(client) => client.generate_changelog(commits, context, debug).await,
+    ) -> Result<ChangelogSummary>;
+
+    /// Group the labeled changes produced by `split::label_changes` into commits.
+    async fn generate_commit_plan(
+        &self,
+        _changes: &str,
+        _context: &CommitContext,
+        _debug: bool,
+    ) -> Result<CommitPlan> {
+        anyhow::bail!("This provider does not support splitting changes into commits")
+    }
+}
+
+/// Settings handed to a provider factory when a client is created.
+#[derive(Debug, Clone)]
+pub struct ProviderOptions {
+    pub api_key: String,
+    pub model: String,
+    pub base_url: Option<String>,
+    pub max_tokens: u32,
+    /// How long Ollama keeps the model loaded after a request (e.g. "5m").
+    pub keep_alive: Option<String>,
+    /// Azure OpenAI deployment name (defaults to the model name).
+    pub azure_deployment: Option<String>,
+    /// Azure OpenAI `api-version` query parameter.
+    pub azure_api_version: Option<String>,
+    /// Stream tokens while generating commit messages (where supported).
+    pub stream: bool,
+    pub retry: RetryPolicy,
+}
+
+impl ProviderOptions {
+    pub fn from_config(config: &AIConfig, api_key: String) -> Self {
+        Self {
+            api_key,
+            model: config.model.clone(),
+            base_url: config.base_url.clone(),
+            max_tokens: config.max_tokens,
+            keep_alive: config.keep_alive.clone(),
+            azure_deployment: config.azure_deployment.clone(),
+            azure_api_version: config.azure_api_version.clone(),
+            stream: config.stream,
+            retry: RetryPolicy {
+                max_retries: config.max_retries,
+                backoff: std::time::Duration::from_millis(config.backoff_ms),
+            },
         }
     }
 }
 
-pub fn create_client(
-    provider: &str,
-    api_key: String,
-    model: String,
-    base_url: Option<String>,
-    max_tokens: u32,
-) -> Result<AIClient> {
-    match provider.to_lowercase().as_str() {
-        "openai" => Ok(AIClient::OpenAI(openai::OpenAIClient::new(
-            api_key, model, base_url, max_tokens,
-        ))),
-        "anthropic" => Ok(AIClient::Anthropic(anthropic::AnthropicClient::new(
-            api_key, model, base_url, max_tokens,
-        ))),
-        _ => anyhow::bail!("Unsupported AI provider: {}", provider),
+pub type ProviderFactory =
+    Box<dyn Fn(ProviderOptions) -> Result<Box<dyn AiProvider>> + Send + Sync>;
+
+/// Maps provider names (as used in `AIConfig.provider`) to client factories.
+pub struct ProviderRegistry {
+    factories: HashMap<String, ProviderFactory>,
+}
+
+impl ProviderRegistry {
+    pub fn new() -> Self {
+        Self {
+            factories: HashMap::new(),
+        }
+    }
+
+    pub fn register<F>(&mut self, name: &str, factory: F)
+    where
+        F: Fn(ProviderOptions) -> Result<Box<dyn AiProvider>> + Send + Sync + 'static,
+    {
+        self.factories
+            .insert(name.to_lowercase(), Box::new(factory));
+    }
+
+    pub fn create(&self, provider: &str, options: ProviderOptions) -> Result<Box<dyn AiProvider>> {
+        match self.factories.get(&provider.to_lowercase()) {
+            Some(factory) => factory(options),
+            None => anyhow::bail!(
+                "Unsupported AI provider: {} (available: {})",
+                provider,
+                self.names().join(", ")
+            ),
+        }
+    }
+
+    pub fn names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.factories.keys().cloned().collect();
+        names.sort();
+        names
+    }
+
+    /// The built-in providers plus the configured `[gateways]`, each an
+    /// OpenAI-compatible client registered under its name.
+    pub fn with_gateways<'a>(
+        gateways: impl IntoIterator<Item = (&'a String, &'a GatewayConfig)>,
+    ) -> Self {
+        let mut registry = Self::default();
+        for (name, gateway) in gateways {
+            let gateway = gateway.clone();
+            registry.register(name, move |opts| {
+                let base_url = opts.base_url.unwrap_or_else(|| gateway.base_url.clone());
+                Ok(Box::new(
+                    openai::OpenAIClient::new(
+                        opts.api_key,
+                        opts.model,
+                        Some(base_url.trim_end_matches('/').to_string()),
+                        opts.max_tokens,
+                    )
+                    .with_headers(gateway.auth_header.clone(), gateway.headers.clone())
+                    .with_streaming(opts.stream)
+                    .with_retry(opts.retry),
+                ))
+            });
+        }
+        registry
+    }
+}
+
+impl Default for ProviderRegistry {
+    fn default() -> Self {
+        let mut registry = Self::new();
+        registry.register("openai", |opts| {
+            Ok(Box::new(
+                openai::OpenAIClient::new(opts.api_key, opts.model, opts.base_url, opts.max_tokens)
+                    .with_streaming(opts.stream)
+                    .with_retry(opts.retry),
+            ))
+        });
+        registry.register("anthropic", |opts| {
+            Ok(Box::new(
+                anthropic::AnthropicClient::new(
+                    opts.api_key,
+                    opts.model,
+                    opts.base_url,
+                    opts.max_tokens,
+                )
+                .with_streaming(opts.stream)
+                .with_retry(opts.retry),
+            ))
+        });
+        registry.register("azure", |opts| {
+            let base_url = opts.base_url.context(
+                "Azure OpenAI requires base_url (e.g. https://<resource>.openai.azure.com)",
+            )?;
+            Ok(Box::new(
+                openai::OpenAIClient::azure(
+                    opts.api_key,
+                    opts.azure_deployment.unwrap_or_else(|| opts.model.clone()),
+                    base_url,
+                    opts.azure_api_version,
+                    opts.max_tokens,
+                )
+                .with_streaming(opts.stream)
+                .with_retry(opts.retry),
+            ))
+        });
+        registry.register("gemini", |opts| {
+            Ok(Box::new(
+                gemini::GeminiClient::new(opts.api_key, opts.model, opts.base_url, opts.max_tokens)
+                    .with_retry(opts.retry),
+            ))
+        });
+        registry.register("ollama", |opts| {
+            Ok(Box::new(
+                ollama::OllamaClient::new(
+                    opts.model,
+                    opts.base_url,
+                    opts.max_tokens,
+                    opts.keep_alive,
+                )
+                .with_retry(opts.retry),
+            ))
+        });
+        registry
     }
 }
 
+/// Whether the given provider needs an API key to be configured.
+pub fn requires_api_key(provider: &str) -> bool {
+    !provider.eq_ignore_ascii_case("ollama")
+}
+
 pub fn build_prompt(diff: &str, context: &CommitContext) -> String {
+    let languages = language::effective_languages(&context.languages);
+    let names: Vec<String> = languages
+        .iter()
+        .map(|l| language::display_name(l))
+        .collect();
+    let primary = &names[0];
+
+    let kind = match names.len() {
+        1 => format!("a structured commit message written in {}", primary),
+        2 => format!(
+            "a bilingual ({} and {}) structured commit message",
+            names[0], names[1]
+        ),
+        n => format!(
+            "a multilingual ({} and {}) structured commit message",
+            names[..n - 1].join(", "),
+            names[n - 1]
+        ),
+    };
+
+    let mut fields = vec![
+        format!("- type: {}", context.style.type_guidance()),
+        format!("- scope: {}", context.style.scope_guidance()),
+        format!(
+            "- description: {} brief description (50 chars or less)",
+            primary
+        ),
+    ];
+    for (i, lang) in languages.iter().enumerate().skip(1) {
+        fields.push(format!(
+            "- {}: {} translation of description (50 chars or less)",
+            language::field_key("description", i, lang),
+            names[i]
+        ));
+    }
+    fields.push(format!(
+        "- body: {} detailed explanation array, each element is one point",
+        primary
+    ));
+    for (i, lang) in languages.iter().enumerate().skip(1) {
+        fields.push(format!(
+            "- {}: {} detailed explanation array, each element corresponds to the {} version",
+            language::field_key("body", i, lang),
+            names[i],
+            primary
+        ));
+    }
+    fields.push("- breaking_change: optional, if there are breaking changes".to_string());
+
+    let mut requirements = Vec::new();
+    if languages.len() == 1 {
+        requirements.push(format!(
+            "description and body must be written in {} only",
+            primary
+        ));
+        requirements
+            .push("body should be an array of strings, each element is one point".to_string());
+    } else {
+        for (i, lang) in languages.iter().enumerate().skip(1) {
+            requirements.push(format!(
+                "description should be in {}, {} should be its {} translation",
+                primary,
+                language::field_key("description", i, lang),
+                names[i]
+            ));
+        }
+        let body_keys: Vec<String> = languages
+            .iter()
+            .enumerate()
+            .map(|(i, lang)| language::field_key("body", i, lang))
+            .collect();
+        requirements.push(format!(
+            "{} should be arrays of strings, each element is one point",
+            body_keys.join(" and ")
+        ));
+        requirements.push(format!(
+            "Each {} point in body should have a corresponding translation at the same position in {}",
+            primary,
+            body_keys[1..].join(" and ")
+        ));
+    }
+    requirements.push("Keep descriptions concise and clear".to_string());
+    requirements.extend(context.style.requirements().iter().map(|r| r.to_string()));
+    requirements.extend(context.rules.iter().cloned());
+    match (&context.common_scope, context.changed_scopes.as_slice()) {
+        _ if !context.enforce_scopes => {
+            if !context.changed_scopes.is_empty() {
+                requirements.push(format!(
+                    "The changed files are in these areas of the repository: {} (most changed first); use one as the scope only if it describes the change well",
+                    context.changed_scopes.join(", ")
+                ));
+            }
+        }
+        (Some(scope), _) => requirements.push(format!(
+            "Use \"{}\" as the scope, all changed files belong to it",
+            scope
+        )),
+        (None, []) if context.known_scopes.is_empty() => {}
+        (None, []) => requirements.push(format!(
+            "scope must be one of the repository's scopes: {}, or null if none fits",
+            context.known_scopes.join(", ")
+        )),
+        (None, scopes) => requirements.push(format!(
+            "scope must be one of the scopes of the changed files: {} (most changed first), or null if the change spans several of them",
+            scopes.join(", ")
+        )),
+    }
+    for hint in &context.hints {
+        requirements.push(format!(
+            "Follow this feedback on an earlier attempt: {}",
+            hint
+        ));
+    }
+
+    let (source, input_title, input) = match &context.stage {
+        PromptStage::Variant { index, total } => {
+            requirements.push(format!(
+                "{} alternative messages are written for this diff and this is number {}, so choose your own wording and emphasis",
+                total, index
+            ));
+            (
+                "the following git diff".to_string(),
+                "Git Diff",
+                budget_diff(diff, context.max_diff_size),
+            )
+        }
+        PromptStage::Full => (
+            "the following git diff".to_string(),
+            "Git Diff",
+            budget_diff(diff, context.max_diff_size),
+        ),
+        PromptStage::Chunk {
+            index,
+            total,
+            label,
+        } => {
+            requirements.push(
+                "Describe only the changes in this part; other parts are summarized separately"
+                    .to_string(),
+            );
+            (
+                format!(
+                    "the following git diff, which is part {} of {} of a larger change (covering {})",
+                    index, total, label
+                ),
+                "Git Diff",
+                budget_diff(diff, context.max_diff_size),
+            )
+        }
+        PromptStage::Merge => {
+            requirements.push(
+                "Describe the change as a whole: choose the type and scope that fit the overall change and merge related points instead of listing every part"
+                    .to_string(),
+            );
+            (
+                "the following summaries of each part of a large change".to_string(),
+                "Part Summaries",
+                diff.to_string(),
+            )
+        }
+        PromptStage::Fix { violations } => {
+            requirements.extend(
+                violations
+                    .iter()
+                    .map(|v| format!("Fix this rule violation: {}", v)),
+            );
+            requirements.push(
+                "Keep the meaning of the message and change only what the rules require"
+                    .to_string(),
+            );
+            (
+                "the following commit message, which breaks the team's commit rules".to_string(),
+                "Commit Message",
+                diff.to_string(),
+            )
+        }
+        PromptStage::Split => {
+            requirements.extend(
+                [
+                    "Group the changes into logical, atomic commits; unrelated changes belong in separate commits",
+                    "Assign every change id to exactly one commit, a file's hunks may go to different commits",
+                    "Order the commits so that each one builds on the previous ones",
+                    "Each commit message describes only the changes of its own commit",
+                ]
+                .map(String::from),
+            );
+            (
+                "the following git diff, in which every change is labeled with an id like [change c1]"
+                    .to_string(),
+                "Labeled Git Diff",
+                budget_diff(diff, context.max_diff_size),
+            )
+        }
+    };
+
+    let (kind, response) = match context.stage {
+        PromptStage::Split => (
+            format!("a list of commits, each with {}", kind),
+            r#"Respond with a JSON object of the form {"groups": [{"changes": ["c1", "c3"], "message": {...}}]}, where each message contains these fields. Example message:"#,
+        ),
+        _ => (
+            kind,
+            "Respond with a JSON object containing these fields. Example:",
+        ),
+    };
+
+    let requirements: Vec<String> = requirements
+        .iter()
+        .enumerate()
+        .map(|(i, r)| format!("{}. {}", i + 1, r))
+        .collect();
+
     format!(
-        r#"You are a Git commit message generator. Based on the following git diff, generate a bilingual (Chinese and English) structured commit message.
+        r#"You are a Git commit message generator. Based on {}, generate {}.
 
 Context:
 - Branch: {}
@@ -184,45 +812,166 @@ Context:
 - Lines added: {}
 - Lines removed: {}
 
-Git Diff:
+{}:
 ```
 {}
 ```
 
-Generate a commit message following the Conventional Commits specification with bilingual format:
-- type: feat, fix, docs, style, refactor, test, chore, perf
-- scope: optional, the component or area affected
-- description: 中文简要描述（50字符以内）
-- description_en: English brief description (50 chars or less)
-- body: 中文详细说明数组，每个元素是一条说明（如："添加了用户认证功能"、"优化了数据库查询性能"）
-- body_en: English detailed explanation array, each element corresponds to Chinese version
-- breaking_change: optional, if there are breaking changes
+{}:
+{}
 
 Important requirements:
-1. description should be in Chinese, description_en should be its English translation
-2. body and body_en should be arrays of strings, each element is one point
-3. Each Chinese point in body should have a corresponding English translation in body_en
-4. Keep descriptions concise and clear
+{}
 
-Respond with a JSON object containing these fields. Example:
-{{
-    "type": "feat",
-    "scope": "auth",
-    "description": "添加用户认证功能",
-    "description_en": "Add user authentication feature",
-    "body": ["实现了JWT令牌验证", "添加了用户登录接口", "集成了OAuth2.0支持"],
-    "body_en": ["Implement JWT token validation", "Add user login endpoint", "Integrate OAuth2.0 support"],
-    "breaking_change": null
-}}
+{}
+{}
 "#,
+        source,
+        kind,
         context.branch_name.as_deref().unwrap_or("unknown"),
         context.file_count,
         context.added_lines,
         context.removed_lines,
-        truncate_diff(diff, 3000)
+        input_title,
+        input,
+        context.style.specification(),
+        fields.join("\n"),
+        requirements.join("\n"),
+        response,
+        example_json(&languages)
+    )
+}
+
+/// Example response in the shape requested from the model.
+fn example_json(languages: &[String]) -> String {
+    let examples: Vec<(String, Vec<String>)> =
+        languages.iter().map(|l| language::example(l)).collect();
+    let quote = |s: &str| serde_json::to_string(s).unwrap_or_default();
+
+    let mut lines = vec![
+        r#"    "type": "feat""#.to_string(),
+        r#"    "scope": "auth""#.to_string(),
+    ];
+    for (i, lang) in languages.iter().enumerate() {
+        lines.push(format!(
+            "    \"{}\": {}",
+            language::field_key("description", i, lang),
+            quote(&examples[i].0)
+        ));
+    }
+    for (i, lang) in languages.iter().enumerate() {
+        let items: Vec<String> = examples[i].1.iter().map(|s| quote(s)).collect();
+        lines.push(format!(
+            "    \"{}\": [{}]",
+            language::field_key("body", i, lang),
+            items.join(", ")
+        ));
+    }
+    lines.push(r#"    "breaking_change": null"#.to_string());
+
+    format!("{{\n{}\n}}", lines.join(",\n"))
+}
+
+/// Fit a diff into `max_chars` bytes without cutting hunks in half.
+///
+/// Every file header is kept. The remaining budget is shared between files,
+/// smallest first so that unused share flows on to larger files, and each
+/// file keeps its most informative hunks. Omitted hunks are replaced by a
+/// stat line. A `max_chars` of 0 disables the limit.
+pub fn budget_diff(diff: &str, max_chars: usize) -> String {
+    if max_chars == 0 || diff.len() <= max_chars {
+        return diff.to_string();
+    }
+
+    let files = crate::git::parse_diff(diff);
+    if files.iter().all(|f| f.path.is_empty()) {
+        // Not a unified diff, nothing to budget by file
+        return truncate_diff(diff, max_chars).to_string();
+    }
+    let reserved: usize = files
+        .iter()
+        .map(|f| f.header.len() + omitted_line(f.hunks.len(), f.added, f.removed).len())
+        .sum();
+    if reserved > max_chars {
+        return stat_summary(&files, max_chars);
+    }
+
+    let mut order: Vec<usize> = (0..files.len()).collect();
+    order.sort_by_key(|&i| files[i].size());
+
+    let mut kept: Vec<Vec<usize>> = vec![Vec::new(); files.len()];
+    let mut remaining = max_chars - reserved;
+    for (n, &i) in order.iter().enumerate() {
+        let share = remaining / (order.len() - n);
+        let hunks = &files[i].hunks;
+
+        // Most changed lines first; prefer the smaller hunk on ties
+        let mut ranked: Vec<usize> = (0..hunks.len()).collect();
+        ranked.sort_by_key(|&h| {
+            let (added, removed) = hunk_changes(&hunks[h]);
+            (std::cmp::Reverse(added + removed), hunks[h].len())
+        });
+
+        let mut used = 0;
+        for h in ranked {
+            if used + hunks[h].len() <= share {
+                used += hunks[h].len();
+                kept[i].push(h);
+            }
+        }
+        kept[i].sort_unstable();
+        remaining -= used;
+    }
+
+    let mut output = String::with_capacity(max_chars);
+    for (file, kept) in files.iter().zip(&kept) {
+        output.push_str(&file.header);
+        let (mut added, mut removed) = (0, 0);
+        for (h, hunk) in file.hunks.iter().enumerate() {
+            if kept.contains(&h) {
+                output.push_str(hunk);
+            } else {
+                let (a, r) = hunk_changes(hunk);
+                added += a;
+                removed += r;
+            }
+        }
+        let omitted = file.hunks.len() - kept.len();
+        if omitted > 0 {
+            output.push_str(&omitted_line(omitted, added, removed));
+        }
+    }
+    output
+}
+
+/// Added and removed line counts of a single hunk.
+fn hunk_changes(hunk: &str) -> (usize, usize) {
+    hunk.lines().skip(1).fold((0, 0), |(added, removed), line| {
+        match line.as_bytes().first() {
+            Some(b'+') => (added + 1, removed),
+            Some(b'-') => (added, removed + 1),
+            _ => (added, removed),
+        }
+    })
+}
+
+fn omitted_line(hunks: usize, added: usize, removed: usize) -> String {
+    format!(
+        "... {} hunk(s) omitted (+{} -{} lines)\n",
+        hunks, added, removed
     )
 }
 
+/// Last resort when not even the file headers fit: one stat line per file.
+fn stat_summary(files: &[crate::git::FileDiff], max_chars: usize) -> String {
+    let summary: String = files
+        .iter()
+        .filter(|f| !f.path.is_empty())
+        .map(|f| format!("{} | +{} -{}\n", f.path, f.added, f.removed))
+        .collect();
+    truncate_diff(&summary, max_chars).to_string()
+}
+
 fn truncate_diff(diff: &str, max_chars: usize) -> &str {
     if diff.len() <= max_chars {
         diff
@@ -236,6 +985,69 @@ fn truncate_diff(diff: &str, max_chars: usize) -> &str {
     }
 }
 
+/// Parse a JSON payload out of raw model output, tolerating markdown fences
+/// and surrounding commentary.
+pub fn parse_json_content<T: DeserializeOwned>(content: &str, provider: &str) -> Result<T> {
+    let content = content.trim();
+
+    // Strip markdown code block wrapper if present
+    let clean_content = if content.starts_with("```") && content.ends_with("```") {
+        content
+            .strip_prefix("```json")
+            .or_else(|| content.strip_prefix("```"))
+            .and_then(|s| s.strip_suffix("```"))
+            .map(|s| s.trim())
+            .unwrap_or(content)
+    } else {
+        content
+    };
+
+    let primary_err = match serde_json::from_str::<T>(clean_content) {
+        Ok(value) => return Ok(value),
+        Err(e) => e,
+    };
+
+    let mut depth = 0;
+    let mut start_idx = None;
+    let mut end_idx = None;
+
+    for (idx, ch) in clean_content.char_indices() {
+        match ch {
+            '{' => {
+                if depth == 0 && start_idx.is_none() {
+                    start_idx = Some(idx);
+                }
+                depth += 1;
+            }
+            '}' => {
+                if depth > 0 {
+                    depth -= 1;
+                }
+                if depth == 0 && start_idx.is_some() {
+                    end_idx = Some(idx + ch.len_utf8());
+                    break;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    match (start_idx, end_idx) {
+        (Some(start), Some(end)) => serde_json::from_str::<T>(&clean_content[start..end])
+            .with_context(|| {
+                format!(
+                    "Failed to parse extracted JSON from {} response: {}",
+                    provider, primary_err
+                )
+            }),
+        _ => anyhow::bail!(
+            "No valid JSON object found in {} response: {}",
+            provider,
+            primary_err
+        ),
+    }
+}
+
 // Changelog generation types and functions
 
 #[derive(Debug, Clone)]
@@ -385,3 +1197,279 @@ Respond with a JSON object. Example:
         commits_text
     )
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct FakeProvider {
+        model: String,
+    }
+
+    #[async_trait]
+    impl AiProvider for FakeProvider {
+        async fn generate_commit_message(
+            &self,
+            _diff: &str,
+            _context: &CommitContext,
+            _debug: bool,
+        ) -> Result<CommitMessage> {
+            Ok(CommitMessage {
+                commit_type: "chore".to_string(),
+                scope: None,
+                description: self.model.clone(),
+                description_en: self.model.clone(),
+                ..Default::default()
+            })
+        }
+
+        async fn generate_changelog(
+            &self,
+            _commits: &[crate::git::CommitInfo],
+            _context: &ChangelogContext,
+            _debug: bool,
+        ) -> Result<ChangelogSummary> {
+            anyhow::bail!("not supported")
+        }
+    }
+
+    fn options() -> ProviderOptions {
+        ProviderOptions {
+            api_key: "key".to_string(),
+            model: "fake-model".to_string(),
+            base_url: None,
+            max_tokens: 100,
+            keep_alive: None,
+            azure_deployment: None,
+            azure_api_version: None,
+            stream: false,
+            retry: RetryPolicy::default(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_registry_creates_registered_provider() {
+        let mut registry = ProviderRegistry::new();
+        registry.register("Fake", |opts| {
+            Ok(Box::new(FakeProvider { model: opts.model }))
+        });
+
+        let client = registry.create("fake", options()).unwrap();
+        let context = CommitContext::for_test();
+        let message = client
+            .generate_commit_message("", &context, false)
+            .await
+            .unwrap();
+        assert_eq!(message.description, "fake-model");
+    }
+
+    #[tokio::test]
+    async fn test_registry_resolves_configured_gateway() {
+        let mut server = mockito::Server::new_async().await;
+        let mock = server
+            .mock("POST", "/v1/chat/completions")
+            .match_header("x-api-key", "key")
+            .match_header("x-team", "platform")
+            .with_status(200)
+            .with_body(
+                serde_json::json!({
+                    "choices": [{
+                        "message": {"content": "{\"type\":\"chore\",\"scope\":null,\"description\":\"更新依赖\",\"description_en\":\"Update dependencies\",\"breaking_change\":null}"},
+                        "finish_reason": "stop"
+                    }]
+                })
+                .to_string(),
+            )
+            .create_async()
+            .await;
+
+        let gateways = std::collections::BTreeMap::from([(
+            "Corp".to_string(),
+            GatewayConfig {
+                base_url: format!("{}/v1/", server.url()),
+                auth_header: Some("X-Api-Key".to_string()),
+                headers: [("X-Team".to_string(), "platform".to_string())].into(),
+            },
+        )]);
+        let registry = ProviderRegistry::with_gateways(&gateways);
+        assert!(registry.names().contains(&"corp".to_string()));
+
+        let client = registry.create("corp", options()).unwrap();
+        let message = client
+            .generate_commit_message("", &CommitContext::for_test(), false)
+            .await
+            .unwrap();
+        assert_eq!(message.description_en, "Update dependencies");
+        mock.assert_async().await;
+    }
+
+    #[test]
+    fn test_registry_rejects_unknown_provider() {
+        let registry = ProviderRegistry::default();
+        let err = registry.create("nope", options()).err().unwrap();
+        assert!(err
+            .to_string()
+            .contains("anthropic, azure, gemini, ollama, openai"));
+    }
+
+    #[test]
+    fn test_format_single_language_has_no_translation_placeholder() {
+        let message: CommitMessage = serde_json::from_str(
+            r#"{"type":"fix","scope":"git","description":"Handle unborn HEAD","body":["Compare index to empty tree"],"breaking_change":null}"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            message.format_conventional(&["en".to_string()], false),
+            "fix(git): Handle unborn HEAD\n\nCompare index to empty tree"
+        );
+        assert!(message
+            .format_conventional(&["en".to_string()], true)
+            .starts_with("🐛 fix(git): Handle unborn HEAD"));
+    }
+
+    #[test]
+    fn test_format_additional_language_from_suffixed_fields() {
+        let message: CommitMessage = serde_json::from_str(
+            r#"{"type":"feat","scope":null,"description":"認証を追加","description_en":"Add auth","description_de":"Auth hinzufügen","body":["JWT検証"],"body_en":["JWT validation"],"breaking_change":null}"#,
+        )
+        .unwrap();
+        let languages = ["ja".to_string(), "en".to_string(), "de".to_string()];
+
+        assert_eq!(
+            message.format_conventional(&languages, false),
+            "feat: 認証を追加\nAdd auth\nAuth hinzufügen\n\nJWT検証\nJWT validation\n[Translation needed]"
+        );
+        assert!(build_prompt(
+            "",
+            &CommitContext {
+                languages: languages.to_vec(),
+                ..Default::default()
+            }
+        )
+        .contains("body_de"));
+    }
+
+    #[test]
+    fn test_render_alternative_styles() {
+        let message: CommitMessage = serde_json::from_str(
+            r#"{"type":"fix","scope":"net","description":"Drop stale sockets.","body":["Close sockets on timeout"],"breaking_change":null}"#,
+        )
+        .unwrap();
+        let languages = ["en".to_string()];
+
+        assert_eq!(
+            message.render(MessageStyle::Gitmoji, &languages, false),
+            "🐛 (net): Drop stale sockets.\n\nClose sockets on timeout"
+        );
+        assert_eq!(
+            message.render(MessageStyle::Angular, &languages, false),
+            "fix(net): drop stale sockets\n\nClose sockets on timeout"
+        );
+        assert_eq!(
+            message.render(MessageStyle::Plain, &languages, false),
+            "Drop stale sockets.\n\nClose sockets on timeout"
+        );
+        assert_eq!(
+            message.render(MessageStyle::Kernel, &languages, false),
+            "net: drop stale sockets\n\nClose sockets on timeout"
+        );
+        assert_eq!(
+            "linux".parse::<MessageStyle>().unwrap(),
+            MessageStyle::Kernel
+        );
+        assert!("nope".parse::<MessageStyle>().is_err());
+    }
+
+    #[test]
+    fn test_split_prompt_asks_for_commit_groups() {
+        let context = CommitContext {
+            languages: vec!["en".to_string()],
+            stage: PromptStage::Split,
+            ..Default::default()
+        };
+        let prompt = build_prompt("@@ -1 +1 @@ [change c1]\n-a\n+b\n", &context);
+
+        assert!(prompt.contains("Labeled Git Diff"));
+        assert!(
+            prompt.contains("generate a list of commits, each with a structured commit message")
+        );
+        assert!(prompt.contains(r#"{"groups": [{"changes": ["c1", "c3"], "message": {...}}]}"#));
+    }
+
+    #[test]
+    fn test_fix_prompt_lists_rules_and_violations() {
+        let context = CommitContext {
+            languages: vec!["en".to_string()],
+            rules: vec!["scope must be one of: cli, git".to_string()],
+            stage: PromptStage::Fix {
+                violations: vec!["scope `ui` is not one of: cli, git".to_string()],
+            },
+            ..Default::default()
+        };
+        let prompt = build_prompt(r#"{"type": "feat", "scope": "ui"}"#, &context);
+
+        assert!(prompt.contains("Commit Message:\n```\n{\"type\": \"feat\""));
+        assert!(prompt.contains(". scope must be one of: cli, git\n"));
+        assert!(prompt.contains("Fix this rule violation: scope `ui` is not one of: cli, git"));
+    }
+
+    #[test]
+    fn test_scope_limited_to_known_scopes() {
+        let mut context = CommitContext {
+            languages: vec!["en".to_string()],
+            common_scope: Some("cli".to_string()),
+            changed_scopes: vec!["cli".to_string()],
+            known_scopes: vec!["cli".to_string(), "git".to_string()],
+            enforce_scopes: true,
+            ..Default::default()
+        };
+        assert!(build_prompt("", &context)
+            .contains("Use \"cli\" as the scope, all changed files belong to it"));
+
+        let mut message = CommitMessage {
+            scope: Some("ui".to_string()),
+            ..Default::default()
+        };
+        context.constrain_scope(&mut message);
+        assert_eq!(message.scope.as_deref(), Some("cli"));
+
+        // A file outside every scope, e.g. README.md, was changed as well
+        context.common_scope = None;
+        assert!(build_prompt("", &context).contains("scopes of the changed files: cli (most"));
+        message.scope = Some("ui".to_string());
+        context.constrain_scope(&mut message);
+        assert_eq!(message.scope, None);
+        message.scope = Some("git".to_string());
+        context.constrain_scope(&mut message);
+        assert_eq!(message.scope.as_deref(), Some("git"));
+
+        // Suggested scopes only guide the prompt
+        context.enforce_scopes = false;
+        context.common_scope = Some("cli".to_string());
+        message.scope = Some("parser".to_string());
+        context.constrain_scope(&mut message);
+        assert_eq!(message.scope.as_deref(), Some("parser"));
+        let prompt = build_prompt("", &context);
+        assert!(prompt.contains("areas of the repository: cli (most changed first)"));
+        assert!(!prompt.contains("Use \"cli\" as the scope"));
+    }
+
+    #[test]
+    fn test_budget_diff_keeps_headers_and_informative_hunks() {
+        let big_hunk = format!("@@ -1,40 +1,40 @@\n{}", " context\n".repeat(40));
+        let diff = format!(
+            "diff --git a/a.rs b/a.rs\n--- a/a.rs\n+++ b/a.rs\n{}@@ -90 +90 @@\n-old\n+new\ndiff --git a/b.rs b/b.rs\n--- a/b.rs\n+++ b/b.rs\n@@ -1 +1,2 @@\n+added\n+more\n",
+            big_hunk
+        );
+
+        let budgeted = budget_diff(&diff, 240);
+        assert!(budgeted.len() <= 240);
+        assert!(budgeted.contains("+++ b/a.rs"));
+        assert!(budgeted.contains("+++ b/b.rs"));
+        assert!(budgeted.contains("+new"));
+        assert!(budgeted.contains("+more"));
+        assert!(budgeted.contains("... 1 hunk(s) omitted (+0 -0 lines)"));
+        assert_eq!(budget_diff(&diff, 0), diff);
+    }
+}
@@ -0,0 +1,274 @@
+use super::retry::RetryPolicy;
+use super::{
+    build_changelog_prompt, build_prompt, check_status, parse_json_content, AiProvider,
+    ChangelogContext, ChangelogSummary, CommitContext, CommitMessage, CommitPlan,
+    CHANGELOG_SYSTEM_PROMPT, COMMIT_SYSTEM_PROMPT, SPLIT_SYSTEM_PROMPT,
+};
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use colored::*;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// Client for the native Ollama `/api/chat` endpoint.
+pub struct OllamaClient {
+    model: String,
+    base_url: String,
+    client: reqwest::Client,
+    retry: RetryPolicy,
+    max_tokens: u32,
+    keep_alive: Option<String>,
+}
+
+impl OllamaClient {
+    pub fn new(
+        model: String,
+        base_url: Option<String>,
+        max_tokens: u32,
+        keep_alive: Option<String>,
+    ) -> Self {
+        // Local models can be slow to load, so allow a longer overall timeout
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(120))
+            .connect_timeout(Duration::from_secs(10))
+            .build()
+            .expect("Failed to create HTTP client");
+
+        Self {
+            model,
+            base_url: base_url
+                .map(|url| url.trim_end_matches('/').to_string())
+                .unwrap_or_else(|| "http://localhost:11434".to_string()),
+            client,
+            retry: RetryPolicy::default(),
+            max_tokens,
+            keep_alive,
+        }
+    }
+
+    pub fn with_retry(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    async fn chat(&self, system: &str, prompt: String, debug: bool) -> Result<String> {
+        let request = OllamaRequest {
+            model: self.model.clone(),
+            messages: vec![
+                OllamaMessage {
+                    role: "system".to_string(),
+                    content: system.to_string(),
+                },
+                OllamaMessage {
+                    role: "user".to_string(),
+                    content: prompt,
+                },
+            ],
+            stream: false,
+            format: "json".to_string(),
+            keep_alive: self.keep_alive.clone(),
+            options: OllamaOptions {
+                temperature: 0.7,
+                num_predict: self.max_tokens,
+            },
+        };
+
+        let response = self
+            .retry
+            .send(
+                || {
+                    self.client
+                        .post(format!("{}/api/chat", self.base_url))
+                        .json(&request)
+                },
+                debug,
+            )
+            .await
+            .context("Failed to send request to Ollama. Is `ollama serve` running?")?;
+        let not_found = response.status() == reqwest::StatusCode::NOT_FOUND;
+        let response =
+            check_status(response, "Ollama", debug)
+                .await
+                .map_err(|e| match not_found {
+                    true => e.context("Run `ollama pull <model>` to download the model"),
+                    false => e,
+                })?;
+
+        let response_text = response
+            .text()
+            .await
+            .context("Failed to read response text")?;
+
+        if debug {
+            println!("\n{}", "=== DEBUG: Raw HTTP Response ===".cyan().bold());
+            println!("{}", response_text);
+            println!("{}", "=================================\n".cyan().bold());
+        }
+
+        let api_response: OllamaResponse =
+            serde_json::from_str(&response_text).context("Failed to parse Ollama response")?;
+
+        if api_response.done_reason.as_deref() == Some("length") {
+            anyhow::bail!("AI response was truncated before completing the JSON (done_reason=length). Try increasing max_tokens or reducing the diff size.");
+        }
+
+        let content = api_response.message.content;
+
+        if debug {
+            println!("\n{}", "=== DEBUG: AI Message Content ===".cyan().bold());
+            println!("{}", content);
+            println!("{}", "==================================\n".cyan().bold());
+        }
+
+        Ok(content)
+    }
+}
+
+#[async_trait]
+impl AiProvider for OllamaClient {
+    async fn generate_commit_message(
+        &self,
+        diff: &str,
+        context: &CommitContext,
+        debug: bool,
+    ) -> Result<CommitMessage> {
+        let content = self
+            .chat(COMMIT_SYSTEM_PROMPT, build_prompt(diff, context), debug)
+            .await?;
+
+        parse_json_content(&content, "Ollama")
+    }
+
+    async fn generate_changelog(
+        &self,
+        commits: &[crate::git::CommitInfo],
+        context: &ChangelogContext,
+        debug: bool,
+    ) -> Result<ChangelogSummary> {
+        let content = self
+            .chat(
+                CHANGELOG_SYSTEM_PROMPT,
+                build_changelog_prompt(commits, context),
+                debug,
+            )
+            .await?;
+
+        parse_json_content(&content, "Ollama")
+    }
+
+    async fn generate_commit_plan(
+        &self,
+        changes: &str,
+        context: &CommitContext,
+        debug: bool,
+    ) -> Result<CommitPlan> {
+        let content = self
+            .chat(SPLIT_SYSTEM_PROMPT, build_prompt(changes, context), debug)
+            .await?;
+
+        parse_json_content(&content, "Ollama")
+    }
+}
+
+#[derive(Serialize)]
+struct OllamaRequest {
+    model: String,
+    messages: Vec<OllamaMessage>,
+    stream: bool,
+    format: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    keep_alive: Option<String>,
+    options: OllamaOptions,
+}
+
+#[derive(Serialize)]
+struct OllamaMessage {
+    role: String,
+    content: String,
+}
+
+#[derive(Serialize)]
+struct OllamaOptions {
+    temperature: f32,
+    num_predict: u32,
+}
+
+#[derive(Deserialize)]
+struct OllamaResponse {
+    message: OllamaResponseMessage,
+    #[serde(default)]
+    done_reason: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct OllamaResponseMessage {
+    content: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mockito::Matcher;
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn test_generate_commit_message_uses_native_chat_api() {
+        let mut server = mockito::Server::new_async().await;
+        let content = r#"{"type":"feat","scope":"ai","description":"添加 Ollama 支持","description_en":"Add Ollama support","body":null,"body_en":null,"breaking_change":null}"#;
+        let mock = server
+            .mock("POST", "/api/chat")
+            .match_body(Matcher::PartialJson(json!({
+                "model": "llama3",
+                "stream": false,
+                "format": "json",
+                "keep_alive": "10m",
+            })))
+            .with_status(200)
+            .with_body(
+                json!({
+                    "model": "llama3",
+                    "message": {"role": "assistant", "content": content},
+                    "done": true,
+                    "done_reason": "stop",
+                })
+                .to_string(),
+            )
+            .create_async()
+            .await;
+
+        let client = OllamaClient::new(
+            "llama3".to_string(),
+            Some(server.url()),
+            500,
+            Some("10m".to_string()),
+        );
+        let message = client
+            .generate_commit_message("+fn main() {}", &CommitContext::for_test(), false)
+            .await
+            .unwrap();
+
+        mock.assert_async().await;
+        assert_eq!(message.commit_type, "feat");
+        assert_eq!(message.scope.as_deref(), Some("ai"));
+        assert_eq!(message.description_en, "Add Ollama support");
+    }
+
+    #[tokio::test]
+    async fn test_missing_model_reports_pull_hint() {
+        let mut server = mockito::Server::new_async().await;
+        server
+            .mock("POST", "/api/chat")
+            .with_status(404)
+            .with_body(r#"{"error":"model 'llama3' not found"}"#)
+            .create_async()
+            .await;
+
+        let client = OllamaClient::new("llama3".to_string(), Some(server.url()), 500, None);
+        let err = client
+            .generate_commit_message("", &CommitContext::for_test(), false)
+            .await
+            .unwrap_err();
+
+        assert!(err.to_string().contains("ollama pull"));
+    }
+}
@@ -1,8 +1,12 @@
+use super::retry::RetryPolicy;
+use super::stream::{is_event_stream, read_sse, TokenEcho, REQUEST_TIMEOUT};
 use super::{
-    build_changelog_prompt, build_prompt, ChangelogContext, ChangelogSummary, CommitContext,
-    CommitMessage,
+    build_changelog_prompt, build_prompt, check_status, parse_json_content, AiProvider,
+    ChangelogContext, ChangelogSummary, CommitContext, CommitMessage, CommitPlan,
+    CHANGELOG_SYSTEM_PROMPT, COMMIT_SYSTEM_PROMPT, SPLIT_SYSTEM_PROMPT,
 };
 use anyhow::{Context, Result};
+use async_trait::async_trait;
 use colored::*;
 use serde::{Deserialize, Serialize};
 use std::time::Duration;
@@ -15,12 +19,12 @@ struct StreamChunk {
 
 #[derive(Deserialize, Debug)]
 struct StreamChoice {
+    #[serde(default)]
     delta: StreamDelta,
-    #[allow(dead_code)]
     finish_reason: Option<String>,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Debug, Default)]
 struct StreamDelta {
     content: Option<String>,
 }
@@ -61,12 +65,26 @@ fn parse_streaming_response(response_text: &str) -> Option<String> {
     }
 }
 
+const DEFAULT_AZURE_API_VERSION: &str = "2024-06-01";
+
+/// Azure OpenAI routes requests by deployment instead of model name
+struct AzureDeployment {
+    name: String,
+    api_version: String,
+}
+
 pub struct OpenAIClient {
     api_key: String,
     model: String,
     base_url: String,
     client: reqwest::Client,
+    retry: RetryPolicy,
     initial_max_tokens: u32,
+    azure: Option<AzureDeployment>,
+    stream: bool,
+    /// Header carrying the API key instead of `Authorization: Bearer`
+    auth_header: Option<String>,
+    headers: Vec<(String, String)>,
 }
 
 impl OpenAIClient {
@@ -77,7 +95,6 @@ impl OpenAIClient {
         initial_max_tokens: u32,
     ) -> Self {
         let client = reqwest::Client::builder()
-            .timeout(Duration::from_secs(30))
             .connect_timeout(Duration::from_secs(10))
             .build()
             .expect("Failed to create HTTP client");
@@ -87,17 +104,254 @@ impl OpenAIClient {
             model,
             base_url: base_url.unwrap_or_else(|| "https://api.openai.com/v1".to_string()),
             client,
+            retry: RetryPolicy::default(),
+            initial_max_tokens,
+            azure: None,
+            stream: false,
+            auth_header: None,
+            headers: Vec::new(),
+        }
+    }
+
+    pub fn with_retry(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    /// Request `stream: true` for commit generation and echo tokens live.
+    pub fn with_streaming(mut self, stream: bool) -> Self {
+        self.stream = stream;
+        self
+    }
+
+    /// Send the API key in `auth_header` (as is) instead of as a bearer
+    /// token, and `headers` with every request, as gateways may require.
+    pub fn with_headers(
+        mut self,
+        auth_header: Option<String>,
+        headers: impl IntoIterator<Item = (String, String)>,
+    ) -> Self {
+        self.auth_header = auth_header;
+        self.headers = headers.into_iter().collect();
+        self
+    }
+
+    /// Create a client for an Azure OpenAI deployment.
+    /// `base_url` is the resource endpoint, e.g. `https://<resource>.openai.azure.com`.
+    pub fn azure(
+        api_key: String,
+        deployment: String,
+        base_url: String,
+        api_version: Option<String>,
+        initial_max_tokens: u32,
+    ) -> Self {
+        let mut client = Self::new(
+            api_key,
+            deployment.clone(),
+            Some(base_url.trim_end_matches('/').to_string()),
             initial_max_tokens,
+        );
+        client.azure = Some(AzureDeployment {
+            name: deployment,
+            api_version: api_version.unwrap_or_else(|| DEFAULT_AZURE_API_VERSION.to_string()),
+        });
+        client
+    }
+
+    /// Build the chat completions request with the endpoint and auth header
+    /// expected by the configured service.
+    fn chat_completions_request(&self) -> reqwest::RequestBuilder {
+        let builder = match &self.azure {
+            Some(azure) => self
+                .client
+                .post(format!(
+                    "{}/openai/deployments/{}/chat/completions",
+                    self.base_url, azure.name
+                ))
+                .query(&[("api-version", azure.api_version.as_str())])
+                .header("api-key", &self.api_key),
+            None => {
+                let builder = self
+                    .client
+                    .post(format!("{}/chat/completions", self.base_url));
+                match &self.auth_header {
+                    Some(name) => builder.header(name, &self.api_key),
+                    None => builder.header("Authorization", format!("Bearer {}", self.api_key)),
+                }
+            }
+        };
+        self.headers.iter().fold(builder, |builder, (name, value)| {
+            builder.header(name, value)
+        })
+    }
+
+    /// Send one chat request and return the message content with its
+    /// finish_reason. With `stream`, tokens are echoed as they arrive.
+    async fn chat(
+        &self,
+        messages: Vec<Message>,
+        max_tokens: u32,
+        stream: bool,
+        debug: bool,
+    ) -> Result<(String, Option<String>)> {
+        let request = OpenAIRequest {
+            model: self.model.clone(),
+            messages,
+            temperature: 0.7,
+            max_tokens,
+            response_format: Some(ResponseFormat {
+                type_field: "json_object".to_string(),
+            }),
+            stream,
+        };
+
+        let response = self
+            .retry
+            .send(
+                || {
+                    let builder = self.chat_completions_request().json(&request);
+                    match stream {
+                        true => builder,
+                        false => builder.timeout(REQUEST_TIMEOUT),
+                    }
+                },
+                debug,
+            )
+            .await
+            .context("Failed to send request to OpenAI")?;
+        let response = check_status(response, "OpenAI", debug).await?;
+
+        let (content, finish_reason) = if stream && is_event_stream(&response) {
+            self.read_stream(response, debug).await?
+        } else {
+            let response_text = response
+                .text()
+                .await
+                .context("Failed to read response text")?;
+
+            if debug {
+                println!("\n{}", "=== DEBUG: Raw HTTP Response ===".cyan().bold());
+                println!("{}", response_text);
+                println!("{}", "=================================\n".cyan().bold());
+            }
+
+            // Try to detect and parse streaming response first
+            if let Some(streamed_content) = parse_streaming_response(&response_text) {
+                if debug {
+                    println!(
+                        "{}",
+                        "=== DEBUG: Detected SSE streaming response ==="
+                            .cyan()
+                            .bold()
+                    );
+                }
+                // For streaming responses, we assume completion when [DONE] is received
+                (streamed_content, Some("stop".to_string()))
+            } else {
+                // Parse as standard OpenAI response
+                let api_response: OpenAIResponse = serde_json::from_str(&response_text)
+                    .context("Failed to parse OpenAI response")?;
+
+                let choice = api_response
+                    .choices
+                    .first()
+                    .ok_or_else(|| anyhow::anyhow!("No response from OpenAI"))?;
+
+                let content = choice
+                    .message
+                    .content
+                    .clone()
+                    .ok_or_else(|| anyhow::anyhow!("Response content is null"))?;
+
+                (content, choice.finish_reason.clone())
+            }
+        };
+
+        if debug {
+            println!("\n{}", "=== DEBUG: AI Message Content ===".cyan().bold());
+            println!("{}", content);
+            println!("{}", "==================================\n".cyan().bold());
         }
+
+        Ok((content, finish_reason))
+    }
+
+    /// A system instruction followed by the user prompt.
+    fn messages(system: &str, prompt: String) -> Vec<Message> {
+        vec![
+            Message {
+                role: "system".to_string(),
+                content: system.to_string(),
+            },
+            Message {
+                role: "user".to_string(),
+                content: prompt,
+            },
+        ]
     }
 
-    pub async fn generate_commit_message(
+    /// Consume a `stream: true` response, echoing tokens as they arrive.
+    /// Returns the concatenated content and the final finish_reason.
+    async fn read_stream(
+        &self,
+        response: reqwest::Response,
+        debug: bool,
+    ) -> Result<(String, Option<String>)> {
+        let mut content = String::new();
+        let mut finish_reason = None;
+        let mut echo = TokenEcho::new();
+
+        let result = read_sse(response, |data| {
+            if data == "[DONE]" {
+                return Ok(());
+            }
+
+            if let Ok(chunk) = serde_json::from_str::<StreamChunk>(data) {
+                for choice in chunk.choices {
+                    if let Some(text) = choice.delta.content {
+                        echo.push(&text);
+                        content.push_str(&text);
+                    }
+                    if choice.finish_reason.is_some() {
+                        finish_reason = choice.finish_reason;
+                    }
+                }
+            } else if debug {
+                eprintln!("Debug: Skipping unrecognized stream event: {}", data);
+            }
+
+            Ok(())
+        })
+        .await;
+        echo.finish();
+        result?;
+
+        if debug {
+            println!(
+                "{}",
+                format!(
+                    "=== DEBUG: Stream finished (finish_reason={}) ===",
+                    finish_reason.as_deref().unwrap_or("none")
+                )
+                .cyan()
+                .bold()
+            );
+        }
+
+        Ok((content, finish_reason))
+    }
+}
+
+#[async_trait]
+impl AiProvider for OpenAIClient {
+    async fn generate_commit_message(
         &self,
         diff: &str,
         context: &CommitContext,
         debug: bool,
     ) -> Result<CommitMessage> {
         let prompt = build_prompt(diff, context);
+        let stream = self.stream && context.stage.streams();
 
         let mut max_tokens = self.initial_max_tokens;
         let max_attempts = 4;
@@ -105,7 +359,7 @@ impl OpenAIClient {
         for attempt in 0..max_attempts {
             let mut messages = vec![Message {
                 role: "system".to_string(),
-                content: "You are a helpful assistant that generates git commit messages in JSON format. Reply with exactly one valid, minified JSON object.".to_string(),
+                content: COMMIT_SYSTEM_PROMPT.to_string(),
             }];
 
             if attempt > 0 {
@@ -120,93 +374,7 @@ impl OpenAIClient {
                 content: prompt.clone(),
             });
 
-            let request = OpenAIRequest {
-                model: self.model.clone(),
-                messages,
-                temperature: 0.7,
-                max_tokens,
-                response_format: Some(ResponseFormat {
-                    type_field: "json_object".to_string(),
-                }),
-            };
-
-            let response = self
-                .client
-                .post(format!("{}/chat/completions", self.base_url))
-                .header("Authorization", format!("Bearer {}", self.api_key))
-                .json(&request)
-                .send()
-                .await
-                .context("Failed to send request to OpenAI")?;
-
-            if !response.status().is_success() {
-                let status = response.status();
-                let error_text = response.text().await?;
-
-                // Sanitize error message to avoid exposing sensitive details
-                let safe_error = match status.as_u16() {
-                    401 => "Authentication failed. Please check your API key.",
-                    403 => "Access forbidden. Please check your API permissions.",
-                    429 => "Rate limit exceeded. Please try again later.",
-                    500..=599 => "OpenAI service error. Please try again later.",
-                    _ => "Request failed. Please check your configuration.",
-                };
-
-                if debug {
-                    eprintln!("Debug: Full error response: {}", error_text);
-                }
-
-                anyhow::bail!("{} (Status: {})", safe_error, status);
-            }
-
-            let response_text = response
-                .text()
-                .await
-                .context("Failed to read response text")?;
-
-            if debug {
-                println!("\n{}", "=== DEBUG: Raw HTTP Response ===".cyan().bold());
-                println!("{}", response_text);
-                println!("{}", "=================================\n".cyan().bold());
-            }
-
-            // Try to detect and parse streaming response first
-            let (content, finish_reason) =
-                if let Some(streamed_content) = parse_streaming_response(&response_text) {
-                    if debug {
-                        println!(
-                            "{}",
-                            "=== DEBUG: Detected SSE streaming response ==="
-                                .cyan()
-                                .bold()
-                        );
-                    }
-                    // For streaming responses, we assume completion when [DONE] is received
-                    (streamed_content, Some("stop".to_string()))
-                } else {
-                    // Parse as standard OpenAI response
-                    let api_response: OpenAIResponse = serde_json::from_str(&response_text)
-                        .context("Failed to parse OpenAI response")?;
-
-                    let choice = api_response
-                        .choices
-                        .first()
-                        .ok_or_else(|| anyhow::anyhow!("No response from OpenAI"))?;
-
-                    let content = choice
-                        .message
-                        .content
-                        .clone()
-                        .ok_or_else(|| anyhow::anyhow!("Response content is null"))?;
-
-                    (content, choice.finish_reason.clone())
-                };
-
-            if debug {
-                println!("\n{}", "=== DEBUG: AI Message Content ===".cyan().bold());
-                println!("{}", content);
-                println!("{}", "==================================\n".cyan().bold());
-            }
+            let (content, finish_reason) = self.chat(messages, max_tokens, stream, debug).await?;
 
             match finish_reason.as_deref() {
                 Some("length") => {
@@ -241,69 +409,7 @@ impl OpenAIClient {
                 }
             }
 
-            // Strip markdown code block wrapper if present
-            let clean_content = if content.starts_with("```json") && content.ends_with("```") {
-                content
-                    .strip_prefix("```json")
-                    .and_then(|s| s.strip_suffix("```"))
-                    .map(|s| s.trim())
-                    .unwrap_or(&content)
-            } else if content.starts_with("```") && content.ends_with("```") {
-                content
-                    .strip_prefix("```")
-                    .and_then(|s| s.strip_suffix("```"))
-                    .map(|s| s.trim())
-                    .unwrap_or(&content)
-            } else {
-                &content
-            };
-
-            let commit_message = match serde_json::from_str::<CommitMessage>(clean_content) {
-                Ok(msg) => msg,
-                Err(primary_err) => {
-                    let mut depth = 0;
-                    let mut start_idx = None;
-                    let mut end_idx = None;
-
-                    for (idx, ch) in clean_content.char_indices() {
-                        match ch {
-                            '{' => {
-                                if depth == 0 && start_idx.is_none() {
-                                    start_idx = Some(idx);
-                                }
-                                depth += 1;
-                            }
-                            '}' => {
-                                if depth > 0 {
-                                    depth -= 1;
-                                }
-                                if depth == 0 && start_idx.is_some() {
-                                    end_idx = Some(idx + ch.len_utf8());
-                                    break;
-                                }
-                            }
-                            _ => {}
-                        }
-                    }
-
-                    if let (Some(start), Some(end)) = (start_idx, end_idx) {
-                        let json_str = &clean_content[start..end];
-                        serde_json::from_str::<CommitMessage>(json_str).with_context(|| {
-                            format!(
-                                "Failed to parse extracted JSON from OpenAI response: {}",
-                                primary_err
-                            )
-                        })?
-                    } else {
-                        return Err(anyhow::anyhow!(
-                            "Failed to parse commit message from OpenAI response: {}",
-                            primary_err
-                        ));
-                    }
-                }
-            };
-
-            return Ok(commit_message);
+            return parse_json_content(&content, "OpenAI");
         }
 
         anyhow::bail!(
@@ -311,172 +417,35 @@ impl OpenAIClient {
         );
     }
 
-    pub async fn generate_changelog(
+    async fn generate_changelog(
         &self,
         commits: &[crate::git::CommitInfo],
         context: &ChangelogContext,
         debug: bool,
     ) -> Result<ChangelogSummary> {
-        let prompt = build_changelog_prompt(commits, context);
-
-        let messages = vec![
-            Message {
-                role: "system".to_string(),
-                content: "You are a helpful assistant that generates changelog summaries in JSON format. Reply with exactly one valid, minified JSON object.".to_string(),
-            },
-            Message {
-                role: "user".to_string(),
-                content: prompt,
-            },
-        ];
-
-        let request = OpenAIRequest {
-            model: self.model.clone(),
-            messages,
-            temperature: 0.7,
-            max_tokens: self.initial_max_tokens,
-            response_format: Some(ResponseFormat {
-                type_field: "json_object".to_string(),
-            }),
-        };
-
-        let response = self
-            .client
-            .post(format!("{}/chat/completions", self.base_url))
-            .header("Authorization", format!("Bearer {}", self.api_key))
-            .json(&request)
-            .send()
-            .await
-            .context("Failed to send request to OpenAI")?;
-
-        if !response.status().is_success() {
-            let status = response.status();
-            let error_text = response.text().await?;
-
-            let safe_error = match status.as_u16() {
-                401 => "Authentication failed. Please check your API key.",
-                403 => "Access forbidden. Please check your API permissions.",
-                429 => "Rate limit exceeded. Please try again later.",
-                500..=599 => "OpenAI service error. Please try again later.",
-                _ => "Request failed. Please check your configuration.",
-            };
-
-            if debug {
-                eprintln!("Debug: Full error response: {}", error_text);
-            }
-
-            anyhow::bail!("{} (Status: {})", safe_error, status);
-        }
-
-        let response_text = response
-            .text()
-            .await
-            .context("Failed to read response text")?;
-
-        if debug {
-            println!("\n{}", "=== DEBUG: Raw HTTP Response ===".cyan().bold());
-            println!("{}", response_text);
-            println!("{}", "=================================\n".cyan().bold());
-        }
-
-        // Try to detect and parse streaming response first
-        let content = if let Some(streamed_content) = parse_streaming_response(&response_text) {
-            if debug {
-                println!(
-                    "{}",
-                    "=== DEBUG: Detected SSE streaming response ==="
-                        .cyan()
-                        .bold()
-                );
-            }
-            streamed_content
-        } else {
-            // Parse as standard OpenAI response
-            let api_response: OpenAIResponse =
-                serde_json::from_str(&response_text).context("Failed to parse OpenAI response")?;
-
-            let choice = api_response
-                .choices
-                .first()
-                .ok_or_else(|| anyhow::anyhow!("No response from OpenAI"))?;
-
-            choice
-                .message
-                .content
-                .clone()
-                .ok_or_else(|| anyhow::anyhow!("Response content is null"))?
-        };
-
-        if debug {
-            println!("\n{}", "=== DEBUG: AI Message Content ===".cyan().bold());
-            println!("{}", content);
-            println!("{}", "==================================\n".cyan().bold());
-        }
-
-        // Strip markdown code block wrapper if present
-        let clean_content = if content.starts_with("```json") && content.ends_with("```") {
-            content
-                .strip_prefix("```json")
-                .and_then(|s| s.strip_suffix("```"))
-                .map(|s| s.trim())
-                .unwrap_or(&content)
-        } else if content.starts_with("```") && content.ends_with("```") {
-            content
-                .strip_prefix("```")
-                .and_then(|s| s.strip_suffix("```"))
-                .map(|s| s.trim())
-                .unwrap_or(&content)
-        } else {
-            &content
-        };
+        let messages = Self::messages(
+            CHANGELOG_SYSTEM_PROMPT,
+            build_changelog_prompt(commits, context),
+        );
+        let (content, _) = self
+            .chat(messages, self.initial_max_tokens, false, debug)
+            .await?;
 
-        let changelog = match serde_json::from_str::<ChangelogSummary>(clean_content) {
-            Ok(summary) => summary,
-            Err(primary_err) => {
-                // Try to extract JSON object
-                let mut depth = 0;
-                let mut start_idx = None;
-                let mut end_idx = None;
-
-                for (idx, ch) in clean_content.char_indices() {
-                    match ch {
-                        '{' => {
-                            if depth == 0 && start_idx.is_none() {
-                                start_idx = Some(idx);
-                            }
-                            depth += 1;
-                        }
-                        '}' => {
-                            if depth > 0 {
-                                depth -= 1;
-                            }
-                            if depth == 0 && start_idx.is_some() {
-                                end_idx = Some(idx + ch.len_utf8());
-                                break;
-                            }
-                        }
-                        _ => {}
-                    }
-                }
+        parse_json_content(&content, "OpenAI")
+    }
 
-                if let (Some(start), Some(end)) = (start_idx, end_idx) {
-                    let json_str = &clean_content[start..end];
-                    serde_json::from_str::<ChangelogSummary>(json_str).with_context(|| {
-                        format!(
-                            "Failed to parse extracted JSON from OpenAI response: {}",
-                            primary_err
-                        )
-                    })?
-                } else {
-                    return Err(anyhow::anyhow!(
-                        "Failed to parse changelog from OpenAI response: {}",
-                        primary_err
-                    ));
-                }
-            }
-        };
+    async fn generate_commit_plan(
+        &self,
+        changes: &str,
+        context: &CommitContext,
+        debug: bool,
+    ) -> Result<CommitPlan> {
+        let messages = Self::messages(SPLIT_SYSTEM_PROMPT, build_prompt(changes, context));
+        let (content, _) = self
+            .chat(messages, self.initial_max_tokens, false, debug)
+            .await?;
 
-        Ok(changelog)
+        parse_json_content(&content, "OpenAI")
     }
 }
 
@@ -487,6 +456,8 @@ struct OpenAIRequest {
     temperature: f32,
     max_tokens: u32,
     response_format: Option<ResponseFormat>,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    stream: bool,
 }
 
 #[derive(Serialize)]
@@ -560,4 +531,83 @@ data: [DONE]"#;
         assert!(result.is_some());
         assert_eq!(result.unwrap(), "hello");
     }
+
+    #[tokio::test]
+    async fn test_azure_uses_deployment_url_and_api_key_header() {
+        let mut server = mockito::Server::new_async().await;
+        let mock = server
+            .mock("POST", "/openai/deployments/gpt4o-prod/chat/completions")
+            .match_query(mockito::Matcher::UrlEncoded(
+                "api-version".into(),
+                "2024-10-21".into(),
+            ))
+            .match_header("api-key", "azure-key")
+            .with_status(200)
+            .with_body(
+                serde_json::json!({
+                    "choices": [{
+                        "message": {"content": "{\"type\":\"docs\",\"scope\":null,\"description\":\"更新文档\",\"description_en\":\"Update docs\",\"breaking_change\":null}"},
+                        "finish_reason": "stop"
+                    }]
+                })
+                .to_string(),
+            )
+            .create_async()
+            .await;
+
+        let client = OpenAIClient::azure(
+            "azure-key".to_string(),
+            "gpt4o-prod".to_string(),
+            server.url(),
+            Some("2024-10-21".to_string()),
+            500,
+        );
+        let context = CommitContext::for_test();
+        let message = client
+            .generate_commit_message("+docs", &context, false)
+            .await
+            .unwrap();
+
+        mock.assert_async().await;
+        assert_eq!(message.commit_type, "docs");
+    }
+
+    #[tokio::test]
+    async fn test_streaming_request_collects_deltas() {
+        let mut server = mockito::Server::new_async().await;
+        let body = [
+            r#"data: {"choices":[{"index":0,"delta":{"content":"{\"type\":\"fix\",\"scope\":null,"},"finish_reason":null}]}"#,
+            r#"data: {"choices":[{"index":0,"delta":{"content":"\"description\":\"修复\",\"description_en\":\"Fix\",\"breaking_change\":null}"},"finish_reason":null}]}"#,
+            r#"data: {"choices":[{"index":0,"delta":{},"finish_reason":"stop"}]}"#,
+            "data: [DONE]",
+        ]
+        .join("\n\n");
+        let mock = server
+            .mock("POST", "/chat/completions")
+            .match_body(mockito::Matcher::PartialJson(
+                serde_json::json!({"stream": true}),
+            ))
+            .with_status(200)
+            .with_header("content-type", "text/event-stream")
+            .with_body(body)
+            .create_async()
+            .await;
+
+        let client = OpenAIClient::new(
+            "key".to_string(),
+            "gpt-4.1".to_string(),
+            Some(server.url()),
+            500,
+        )
+        .with_streaming(true);
+        let context = CommitContext::for_test();
+        let message = client
+            .generate_commit_message("-a\n+b", &context, false)
+            .await
+            .unwrap();
+
+        mock.assert_async().await;
+        assert_eq!(message.commit_type, "fix");
+        assert_eq!(message.description, "修复");
+    }
 }
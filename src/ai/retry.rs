@@ -0,0 +1,164 @@
+use anyhow::Result;
+use colored::*;
+use reqwest::{RequestBuilder, Response, StatusCode};
+use std::time::Duration;
+
+/// Upper bound for a single wait, regardless of backoff growth or `Retry-After`.
+const MAX_DELAY: Duration = Duration::from_secs(60);
+
+/// Retry policy for transient API failures: 429, 5xx and connection errors.
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    pub max_retries: u32,
+    /// Delay before the first retry; doubled after every attempt
+    pub backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 3,
+            backoff: Duration::from_millis(1000),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Send the request built by `build`, retrying transient failures.
+    ///
+    /// Non-retryable responses (and the last response once retries are
+    /// exhausted) are returned as-is so callers keep their own error handling.
+    pub async fn send<F>(&self, build: F, debug: bool) -> Result<Response>
+    where
+        F: Fn() -> RequestBuilder,
+    {
+        let mut attempt = 0;
+
+        loop {
+            let can_retry = attempt < self.max_retries;
+
+            match build().send().await {
+                Ok(response) if can_retry && is_retryable_status(response.status()) => {
+                    let delay = retry_after(&response).unwrap_or_else(|| self.backoff_for(attempt));
+                    report_retry(
+                        &response.status().to_string(),
+                        delay,
+                        attempt,
+                        self.max_retries,
+                    );
+                    tokio::time::sleep(delay).await;
+                }
+                Ok(response) => return Ok(response),
+                Err(e) if can_retry && (e.is_connect() || e.is_timeout()) => {
+                    let delay = self.backoff_for(attempt);
+                    if debug {
+                        eprintln!("Debug: Request error: {}", e);
+                    }
+                    report_retry("connection error", delay, attempt, self.max_retries);
+                    tokio::time::sleep(delay).await;
+                }
+                Err(e) => return Err(e.into()),
+            }
+
+            attempt += 1;
+        }
+    }
+
+    fn backoff_for(&self, attempt: u32) -> Duration {
+        self.backoff
+            .saturating_mul(2u32.saturating_pow(attempt))
+            .min(MAX_DELAY)
+    }
+}
+
+fn is_retryable_status(status: StatusCode) -> bool {
+    status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error()
+}
+
+/// Parse the `Retry-After` header (delay in seconds).
+fn retry_after(response: &Response) -> Option<Duration> {
+    response
+        .headers()
+        .get(reqwest::header::RETRY_AFTER)?
+        .to_str()
+        .ok()?
+        .trim()
+        .parse::<f64>()
+        .ok()
+        .filter(|secs| secs.is_finite() && *secs >= 0.0)
+        .map(|secs| Duration::from_secs_f64(secs).min(MAX_DELAY))
+}
+
+fn report_retry(reason: &str, delay: Duration, attempt: u32, max_retries: u32) {
+    eprintln!(
+        "{}",
+        format!(
+            "Request failed ({}), retrying in {:.1}s ({}/{})...",
+            reason,
+            delay.as_secs_f64(),
+            attempt + 1,
+            max_retries
+        )
+        .yellow()
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backoff_doubles_and_is_capped() {
+        let policy = RetryPolicy {
+            max_retries: 10,
+            backoff: Duration::from_millis(500),
+        };
+        assert_eq!(policy.backoff_for(0), Duration::from_millis(500));
+        assert_eq!(policy.backoff_for(2), Duration::from_millis(2000));
+        assert_eq!(policy.backoff_for(20), MAX_DELAY);
+    }
+
+    #[tokio::test]
+    async fn test_retries_server_errors_until_exhausted() {
+        let mut server = mockito::Server::new_async().await;
+        let mock = server
+            .mock("POST", "/")
+            .with_status(503)
+            .with_header("retry-after", "0")
+            .expect(3)
+            .create_async()
+            .await;
+
+        let policy = RetryPolicy {
+            max_retries: 2,
+            backoff: Duration::ZERO,
+        };
+        let client = reqwest::Client::new();
+        let url = server.url();
+        let response = policy.send(|| client.post(&url), false).await.unwrap();
+
+        mock.assert_async().await;
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+    }
+
+    #[tokio::test]
+    async fn test_does_not_retry_client_errors() {
+        let mut server = mockito::Server::new_async().await;
+        let mock = server
+            .mock("POST", "/")
+            .with_status(401)
+            .expect(1)
+            .create_async()
+            .await;
+
+        let client = reqwest::Client::new();
+        let url = server.url();
+        let response = RetryPolicy::default()
+            .send(|| client.post(&url), false)
+            .await
+            .unwrap();
+
+        mock.assert_async().await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    }
+}
@@ -0,0 +1,363 @@
+//! Split a working tree with unrelated changes into several commits.
+//!
+//! Every hunk (or whole file, for files without hunks such as binaries) is
+//! labeled with an id like `c3`. The model groups the ids into commits, and
+//! the plan is checked so that each change lands in exactly one commit.
+//! Lockfiles, generated and binary files are shown as a one-line summary and
+//! labeled as a whole. Changes the model did not assign (e.g. because they
+//! were cut from an oversized prompt) are left for the user to place.
+
+use super::{CommitContext, CommitMessage, PromptStage, ProviderChain};
+use crate::git::FileDiff;
+use anyhow::Result;
+use serde::Deserialize;
+use std::collections::BTreeSet;
+
+/// Commits proposed by the model for a labeled diff.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct CommitPlan {
+    #[serde(default)]
+    pub groups: Vec<PlannedCommit>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct PlannedCommit {
+    /// Change ids, e.g. `["c1", "c3"]`
+    #[serde(default)]
+    pub changes: Vec<String>,
+    pub message: CommitMessage,
+}
+
+/// A hunk of a file, or the whole file when it has no hunks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ChangeRef {
+    pub file: usize,
+    pub hunk: Option<usize>,
+}
+
+/// Changes committed together, with their rendered message. The group of
+/// unassigned changes has an empty message until the user writes one.
+#[derive(Debug, Clone)]
+pub struct CommitGroup {
+    pub changes: Vec<ChangeRef>,
+    pub message: String,
+}
+
+impl CommitGroup {
+    /// Whether this holds the changes the model did not assign.
+    pub fn is_unassigned(&self) -> bool {
+        self.message.trim().is_empty()
+    }
+
+    /// Indices of the files touched by this group, in order.
+    pub fn files(&self) -> Vec<usize> {
+        let mut files: Vec<usize> = self.changes.iter().map(|c| c.file).collect();
+        files.dedup();
+        files
+    }
+}
+
+/// Label every change of `files`. Returns the labeled diff and the change
+/// behind each id: `c1` is `refs[0]`.
+pub fn label_changes(files: &[FileDiff]) -> (String, Vec<ChangeRef>) {
+    let mut text = String::new();
+    let mut refs = Vec::new();
+
+    for (file, diff) in files.iter().enumerate() {
+        if let Some(summary) = &diff.summary {
+            refs.push(ChangeRef { file, hunk: None });
+            text.push_str(&format!(
+                "{} [change c{}: whole file]\n",
+                summary.trim_end(),
+                refs.len()
+            ));
+            continue;
+        }
+        text.push_str(&diff.header);
+        if diff.hunks.is_empty() {
+            refs.push(ChangeRef { file, hunk: None });
+            text.push_str(&format!("[change c{}: whole file]\n", refs.len()));
+            continue;
+        }
+        for (hunk, content) in diff.hunks.iter().enumerate() {
+            refs.push(ChangeRef {
+                file,
+                hunk: Some(hunk),
+            });
+            let (header, rest) = content.split_once('\n').unwrap_or((content, ""));
+            text.push_str(&format!("{} [change c{}]\n{}", header, refs.len(), rest));
+        }
+    }
+
+    (text, refs)
+}
+
+/// Turn the model's plan into commit groups. Unknown ids are ignored, an id
+/// listed twice stays in its first group, and changes the model left out are
+/// collected in a final unassigned group so that nothing is lost.
+pub fn resolve_plan(
+    plan: CommitPlan,
+    refs: &[ChangeRef],
+    context: &CommitContext,
+    include_emoji: bool,
+) -> Result<Vec<CommitGroup>> {
+    let mut assigned = vec![false; refs.len()];
+    let mut groups = Vec::new();
+
+    for mut planned in plan.groups {
+        context.constrain_scope(&mut planned.message);
+        let mut changes: Vec<ChangeRef> = planned
+            .changes
+            .iter()
+            .filter_map(|id| parse_change_id(id))
+            .filter(|&i| i < refs.len() && !std::mem::replace(&mut assigned[i], true))
+            .map(|i| refs[i])
+            .collect();
+        if changes.is_empty() {
+            continue;
+        }
+        changes.sort();
+        groups.push(CommitGroup {
+            changes,
+            message: planned
+                .message
+                .render(context.style, &context.languages, include_emoji),
+        });
+    }
+
+    if groups.is_empty() {
+        anyhow::bail!("The AI did not assign any changes to a commit");
+    }
+    let leftover: Vec<ChangeRef> = refs
+        .iter()
+        .zip(&assigned)
+        .filter(|(_, assigned)| !**assigned)
+        .map(|(change, _)| *change)
+        .collect();
+    if !leftover.is_empty() {
+        groups.push(CommitGroup {
+            changes: leftover,
+            message: String::new(),
+        });
+    }
+
+    Ok(groups)
+}
+
+/// `"c3"`, `"[change c3]"` or `"3"` to the index 2.
+fn parse_change_id(id: &str) -> Option<usize> {
+    let digits: String = id.chars().filter(|c| c.is_ascii_digit()).collect();
+    digits.parse::<usize>().ok()?.checked_sub(1)
+}
+
+/// Ask the model to group the changes of `files` into commits.
+pub async fn plan_commits<'a>(
+    chain: &'a ProviderChain,
+    files: &[FileDiff],
+    context: &CommitContext,
+    include_emoji: bool,
+    debug: bool,
+) -> Result<(Vec<CommitGroup>, &'a str)> {
+    let (labeled, refs) = label_changes(files);
+    let context = CommitContext {
+        stage: PromptStage::Split,
+        ..context.clone()
+    };
+
+    let (plan, provider) = chain
+        .generate_commit_plan(&labeled, &context, debug)
+        .await?;
+    Ok((
+        resolve_plan(plan, &refs, &context, include_emoji)?,
+        provider,
+    ))
+}
+
+/// Merge the groups at `indices`, in any order, into the earliest of them,
+/// keeping the first message that is not empty. Indices out of range are
+/// ignored.
+pub fn merge_groups(groups: &mut Vec<CommitGroup>, indices: &[usize]) {
+    let indices: BTreeSet<usize> = indices
+        .iter()
+        .copied()
+        .filter(|&i| i < groups.len())
+        .collect();
+    let mut indices = indices.into_iter();
+    let Some(target) = indices.next() else {
+        return;
+    };
+    let rest: Vec<usize> = indices.collect();
+
+    for &i in &rest {
+        let merged = groups[i].clone();
+        groups[target].changes.extend(merged.changes);
+        if groups[target].is_unassigned() {
+            groups[target].message = merged.message;
+        }
+    }
+    groups[target].changes.sort();
+    // Remove from the back so the remaining indices stay valid
+    for &i in rest.iter().rev() {
+        groups.remove(i);
+    }
+}
+
+/// Tracks which hunks are already staged while groups are committed one
+/// after another. Staging a hunk removes it from the file's unstaged diff,
+/// so later groups address the remaining hunks by their new positions.
+pub struct StagingState {
+    staged: Vec<Vec<bool>>,
+}
+
+/// What to stage of one file for a group.
+#[derive(Debug, PartialEq, Eq)]
+pub enum StageStep {
+    /// Everything that is still unstaged in the file
+    WholeFile(usize),
+    /// Hunks at these positions of the file's current unstaged diff
+    Hunks(usize, Vec<usize>),
+}
+
+impl StagingState {
+    pub fn new(files: &[FileDiff]) -> Self {
+        Self {
+            staged: files.iter().map(|f| vec![false; f.hunks.len()]).collect(),
+        }
+    }
+
+    /// Steps staging the changes of `group`, marking them as staged.
+    pub fn take(&mut self, group: &CommitGroup) -> Vec<StageStep> {
+        let mut steps = Vec::new();
+        for file in group.files() {
+            let hunks: Vec<usize> = group
+                .changes
+                .iter()
+                .filter(|c| c.file == file)
+                .filter_map(|c| c.hunk)
+                .collect();
+            let staged = &mut self.staged[file];
+
+            let positions: Vec<usize> = hunks
+                .iter()
+                .map(|&h| staged[..h].iter().filter(|s| !**s).count())
+                .collect();
+            let remaining = staged.iter().filter(|s| !**s).count();
+            for &h in &hunks {
+                staged[h] = true;
+            }
+
+            if hunks.is_empty() || positions.len() == remaining {
+                steps.push(StageStep::WholeFile(file));
+            } else {
+                steps.push(StageStep::Hunks(file, positions));
+            }
+        }
+        steps
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::git::parse_diff;
+
+    fn files() -> Vec<FileDiff> {
+        parse_diff(concat!(
+            "diff --git a/a.rs b/a.rs\n--- a/a.rs\n+++ b/a.rs\n",
+            "@@ -1 +1 @@\n-a\n+b\n@@ -10 +10 @@\n-c\n+d\n@@ -20 +20 @@\n-e\n+f\n",
+            "diff --git a/logo.png b/logo.png\nBinary files differ\n",
+        ))
+    }
+
+    fn planned(changes: &[&str], description: &str) -> PlannedCommit {
+        PlannedCommit {
+            changes: changes.iter().map(|c| c.to_string()).collect(),
+            message: CommitMessage {
+                commit_type: "feat".to_string(),
+                description: description.to_string(),
+                ..Default::default()
+            },
+        }
+    }
+
+    #[test]
+    fn test_label_and_resolve_plan() {
+        let files = files();
+        let (labeled, refs) = label_changes(&files);
+        assert!(labeled.contains("@@ -10 +10 @@ [change c2]\n-c\n"));
+        assert!(labeled.contains("[change c4: whole file]"));
+        assert_eq!(refs.len(), 4);
+
+        let mut summarized = files.clone();
+        summarized[0].summary = Some("a.rs: generated file updated (6 lines)\n".to_string());
+        let (labeled, summarized_refs) = label_changes(&summarized);
+        assert!(
+            labeled.starts_with("a.rs: generated file updated (6 lines) [change c1: whole file]\n")
+        );
+        assert!(!labeled.contains("-c\n"));
+        assert_eq!(
+            summarized_refs[0],
+            ChangeRef {
+                file: 0,
+                hunk: None
+            }
+        );
+
+        let plan = CommitPlan {
+            groups: vec![
+                planned(&["c2", "c9"], "one"),
+                planned(&["[change c2]"], "empty"),
+                planned(&["c4", "1"], "two"),
+            ],
+        };
+        let context = CommitContext {
+            languages: vec!["en".to_string()],
+            ..Default::default()
+        };
+        let groups = resolve_plan(plan, &refs, &context, false).unwrap();
+
+        assert_eq!(groups.len(), 3);
+        assert_eq!(groups[0].changes, vec![refs[1]]);
+        assert!(groups[1].message.starts_with("feat: two"));
+        assert_eq!(groups[1].changes, vec![refs[0], refs[3]]);
+        // c3 was left out and is shown as unassigned
+        assert!(groups[2].is_unassigned());
+        assert_eq!(groups[2].changes, vec![refs[2]]);
+
+        let mut merged = groups.clone();
+        merge_groups(&mut merged, &[2, 0, 2, 7]);
+        assert_eq!(merged.len(), 2);
+        assert_eq!(merged[0].changes, vec![refs[1], refs[2]]);
+        assert!(merged[0].message.starts_with("feat: one"));
+
+        // An unassigned group first takes the earliest message after it
+        let mut reordered = groups.clone();
+        reordered.rotate_right(1);
+        merge_groups(&mut reordered, &[2, 0, 1]);
+        assert_eq!(reordered.len(), 1);
+        assert!(reordered[0].message.starts_with("feat: one"));
+    }
+
+    #[test]
+    fn test_staging_state_renumbers_remaining_hunks() {
+        let files = files();
+        let (_, refs) = label_changes(&files);
+        let group = |changes: &[usize]| CommitGroup {
+            changes: changes.iter().map(|&i| refs[i]).collect(),
+            message: String::new(),
+        };
+        let mut state = StagingState::new(&files);
+
+        assert_eq!(state.take(&group(&[1])), vec![StageStep::Hunks(0, vec![1])]);
+        assert_eq!(state.take(&group(&[2])), vec![StageStep::Hunks(0, vec![1])]);
+        assert_eq!(
+            state.take(&group(&[0, 3])),
+            vec![StageStep::WholeFile(0), StageStep::WholeFile(1)]
+        );
+
+        let mut groups = vec![group(&[0]), group(&[1]), group(&[2, 3])];
+        merge_groups(&mut groups, &[0, 2]);
+        assert_eq!(groups.len(), 2);
+        assert_eq!(groups[0].changes, vec![refs[0], refs[2], refs[3]]);
+    }
+}
@@ -0,0 +1,155 @@
+use anyhow::{Context, Result};
+use colored::*;
+use std::io::{IsTerminal, Write};
+use std::time::Duration;
+
+/// Longest wait for the next chunk of a streamed response. A stream has no
+/// total timeout, since a long generation may take minutes to finish.
+pub const IDLE_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Total timeout of a request whose response is not streamed, set per
+/// request by the streaming clients. Streamed responses are only limited by
+/// `IDLE_TIMEOUT` between chunks.
+pub(super) const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Incrementally splits a Server-Sent Events byte stream into `data:` payloads.
+///
+/// Chunks may end in the middle of a line (or a UTF-8 sequence), so bytes are
+/// buffered until a full line is available.
+#[derive(Default)]
+pub struct SseDecoder {
+    buffer: Vec<u8>,
+}
+
+impl SseDecoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Feed a chunk of bytes and return the payloads of all completed `data:` lines.
+    pub fn push(&mut self, chunk: &[u8]) -> Vec<String> {
+        self.buffer.extend_from_slice(chunk);
+
+        let mut payloads = Vec::new();
+        while let Some(pos) = self.buffer.iter().position(|&b| b == b'\n') {
+            let line: Vec<u8> = self.buffer.drain(..=pos).collect();
+            if let Some(data) = parse_data_line(&line) {
+                payloads.push(data);
+            }
+        }
+        payloads
+    }
+
+    /// Flush a trailing line that was not terminated by a newline.
+    pub fn finish(&mut self) -> Vec<String> {
+        let line = std::mem::take(&mut self.buffer);
+        parse_data_line(&line).into_iter().collect()
+    }
+}
+
+fn parse_data_line(line: &[u8]) -> Option<String> {
+    let line = String::from_utf8_lossy(line);
+    let line = line.trim();
+    line.strip_prefix("data:")
+        .map(|data| data.trim_start().to_string())
+}
+
+/// Whether the server actually answered with an SSE stream. Some
+/// OpenAI-compatible proxies ignore `stream: true` and return plain JSON.
+pub fn is_event_stream(response: &reqwest::Response) -> bool {
+    response
+        .headers()
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("text/event-stream"))
+}
+
+/// Read an SSE response body to the end, calling `on_data` for every `data:` payload.
+pub async fn read_sse<F>(mut response: reqwest::Response, mut on_data: F) -> Result<()>
+where
+    F: FnMut(&str) -> Result<()>,
+{
+    let mut decoder = SseDecoder::new();
+
+    loop {
+        let chunk = tokio::time::timeout(IDLE_TIMEOUT, response.chunk())
+            .await
+            .map_err(|_| {
+                anyhow::anyhow!(
+                    "The streaming response stalled for {}s",
+                    IDLE_TIMEOUT.as_secs()
+                )
+            })?
+            .context("Failed to read streaming response")?;
+        let Some(chunk) = chunk else {
+            break;
+        };
+        for data in decoder.push(&chunk) {
+            on_data(&data)?;
+        }
+    }
+
+    for data in decoder.finish() {
+        on_data(&data)?;
+    }
+
+    Ok(())
+}
+
+/// Echoes streamed tokens to the terminal as they arrive.
+pub struct TokenEcho {
+    enabled: bool,
+    printed: bool,
+}
+
+impl TokenEcho {
+    pub fn new() -> Self {
+        Self {
+            enabled: std::io::stderr().is_terminal(),
+            printed: false,
+        }
+    }
+
+    pub fn push(&mut self, text: &str) {
+        if !self.enabled || text.is_empty() {
+            return;
+        }
+        let mut stderr = std::io::stderr();
+        let _ = write!(stderr, "{}", text.dimmed());
+        let _ = stderr.flush();
+        self.printed = true;
+    }
+
+    pub fn finish(&mut self) {
+        if self.printed {
+            eprintln!();
+            self.printed = false;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decoder_handles_split_lines_and_utf8() {
+        let mut decoder = SseDecoder::new();
+        let event = "data: {\"text\":\"提交\"}\n\ndata: [DONE]\n".as_bytes();
+        // Split inside the multi-byte character
+        let split = event.iter().position(|&b| b == 0xe6).unwrap() + 1;
+
+        assert!(decoder.push(&event[..split]).is_empty());
+        let payloads = decoder.push(&event[split..]);
+        assert_eq!(payloads, vec!["{\"text\":\"提交\"}", "[DONE]"]);
+        assert!(decoder.finish().is_empty());
+    }
+
+    #[test]
+    fn test_decoder_ignores_event_lines_and_flushes_tail() {
+        let mut decoder = SseDecoder::new();
+        let payloads = decoder.push(b"event: ping\r\ndata: {}\r\n\r\ndata: tail");
+        assert_eq!(payloads, vec!["{}"]);
+        assert_eq!(decoder.finish(), vec!["tail"]);
+    }
+}
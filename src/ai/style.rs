@@ -0,0 +1,156 @@
+use std::fmt;
+use std::str::FromStr;
+
+/// Commit message style selected by `commit.format`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MessageStyle {
+    /// `type(scope): description` (Conventional Commits)
+    #[default]
+    Conventional,
+    /// `<emoji> (scope): description` (gitmoji)
+    Gitmoji,
+    /// Conventional header restricted to Angular's types and subject rules
+    Angular,
+    /// Summary line and body without any type prefix
+    Plain,
+    /// Linux kernel `subsystem: summary`
+    Kernel,
+}
+
+impl MessageStyle {
+    pub const NAMES: &'static [&'static str] =
+        &["conventional", "gitmoji", "angular", "plain", "kernel"];
+
+    /// Opening instruction describing the overall format.
+    pub fn specification(&self) -> &'static str {
+        match self {
+            MessageStyle::Conventional => {
+                "Generate a commit message following the Conventional Commits specification"
+            }
+            MessageStyle::Gitmoji => {
+                "Generate a commit message following the gitmoji convention (the type is rendered as an emoji in front of the summary)"
+            }
+            MessageStyle::Angular => {
+                "Generate a commit message strictly following the Angular commit message guidelines"
+            }
+            MessageStyle::Plain => {
+                "Generate a plain commit message: a concise summary line followed by an explanatory body, without any type prefix in the summary"
+            }
+            MessageStyle::Kernel => {
+                "Generate a commit message in the Linux kernel style, where the summary line is `subsystem: summary`"
+            }
+        }
+    }
+
+    /// Commit types the model may choose from.
+    pub fn allowed_types(&self) -> &'static [&'static str] {
+        match self {
+            MessageStyle::Angular => &[
+                "build", "ci", "docs", "feat", "fix", "perf", "refactor", "test",
+            ],
+            MessageStyle::Gitmoji => &[
+                "feat", "fix", "docs", "style", "refactor", "test", "chore", "perf", "build", "ci",
+                "revert",
+            ],
+            _ => &[
+                "feat", "fix", "docs", "style", "refactor", "test", "chore", "perf",
+            ],
+        }
+    }
+
+    pub fn type_guidance(&self) -> String {
+        let types = self.allowed_types().join(", ");
+        match self {
+            MessageStyle::Plain | MessageStyle::Kernel => format!(
+                "{} (used for classification only, not shown in the summary)",
+                types
+            ),
+            _ => types,
+        }
+    }
+
+    pub fn scope_guidance(&self) -> &'static str {
+        match self {
+            MessageStyle::Angular => {
+                "optional, the name of the affected package or module (lowercase, no spaces)"
+            }
+            MessageStyle::Kernel => {
+                "required, the subsystem or path prefix affected (e.g. net, mm, drivers/usb)"
+            }
+            _ => "optional, the component or area affected",
+        }
+    }
+
+    /// Additional style-specific rules appended to the prompt requirements.
+    pub fn requirements(&self) -> &'static [&'static str] {
+        match self {
+            MessageStyle::Conventional => &[],
+            MessageStyle::Gitmoji => &[
+                "Pick the type that best matches the intention of the change, it selects the emoji",
+            ],
+            MessageStyle::Angular => &[
+                "Write the description in the imperative, present tense (\"change\" not \"changed\")",
+                "Do not capitalize the first letter of the description and do not end it with a period",
+                "The body should explain the motivation for the change",
+            ],
+            MessageStyle::Plain => &[
+                "Write the description as a short imperative summary that stands on its own",
+            ],
+            MessageStyle::Kernel => &[
+                "Write the description as an imperative summary starting with a lowercase letter, without a trailing period",
+                "The body should describe the problem and why the change is needed, not only what changed",
+            ],
+        }
+    }
+}
+
+impl FromStr for MessageStyle {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "" | "conventional" => Ok(MessageStyle::Conventional),
+            "gitmoji" => Ok(MessageStyle::Gitmoji),
+            "angular" => Ok(MessageStyle::Angular),
+            "plain" => Ok(MessageStyle::Plain),
+            "kernel" | "linux" => Ok(MessageStyle::Kernel),
+            other => anyhow::bail!(
+                "Unsupported commit format: {} (expected one of: {})",
+                other,
+                Self::NAMES.join(", ")
+            ),
+        }
+    }
+}
+
+impl fmt::Display for MessageStyle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            MessageStyle::Conventional => "conventional",
+            MessageStyle::Gitmoji => "gitmoji",
+            MessageStyle::Angular => "angular",
+            MessageStyle::Plain => "plain",
+            MessageStyle::Kernel => "kernel",
+        };
+        f.write_str(name)
+    }
+}
+
+/// Emoji for a commit type, following gitmoji where possible.
+pub fn emoji_for_type(commit_type: &str) -> &'static str {
+    match commit_type {
+        "feat" => "✨",
+        "fix" => "🐛",
+        "docs" => "📝",
+        "style" => "🎨",
+        "refactor" => "♻️",
+        "test" => "✅",
+        "chore" => "🔧",
+        "perf" => "⚡️",
+        "build" => "📦️",
+        "ci" => "👷",
+        "revert" => "⏪️",
+        "security" => "🔒️",
+        _ => "🔨",
+    }
+}
@@ -38,6 +38,57 @@ pub enum Commands {
 
         #[arg(long, help = "Debug mode - show AI raw response")]
         debug: bool,
+
+        #[arg(
+            long,
+            help = "Summarize large diffs in chunks, then merge into one message"
+        )]
+        map_reduce: bool,
+
+        #[arg(
+            long,
+            help = "Rewrite the last commit's message (includes newly staged changes)"
+        )]
+        amend: bool,
+
+        #[arg(
+            long,
+            default_value = "1",
+            value_parser = clap::value_parser!(u8).range(1..=10),
+            help = "Generate N candidate messages and pick one"
+        )]
+        candidates: u8,
+    },
+
+    /// Split uncommitted changes into several commits using AI
+    Split {
+        #[arg(long, help = "API key for AI service (or set OPENAI_API_KEY env var)")]
+        api_key: Option<String>,
+
+        #[arg(long, help = "AI model to use (overrides config)")]
+        model: Option<String>,
+
+        #[arg(long, help = "Custom API base URL (e.g., https://api.openai.com/v1)")]
+        base_url: Option<String>,
+
+        #[arg(long, help = "Debug mode - show AI raw response")]
+        debug: bool,
+    },
+
+    /// Check commit messages against the [lint] rules
+    Lint {
+        /// Commits to check: a range such as origin/main..HEAD or a single commit (default: HEAD)
+        range: Option<String>,
+
+        #[arg(
+            long,
+            conflicts_with = "range",
+            help = "Check the message in this file instead (e.g. in a commit-msg hook)"
+        )]
+        file: Option<PathBuf>,
+
+        #[arg(long, help = "Print the result as JSON")]
+        json: bool,
     },
 
     /// Show git diff
@@ -55,6 +106,25 @@ pub enum Commands {
         force: bool,
     },
 
+    /// Install a prepare-commit-msg hook so `git commit` gets AI messages
+    InstallHook {
+        #[arg(long, help = "Replace an existing prepare-commit-msg hook")]
+        force: bool,
+    },
+
+    /// Fill in the commit message file (run by the prepare-commit-msg hook)
+    #[command(hide = true)]
+    PrepareCommitMsg {
+        /// Path of the commit message file
+        file: PathBuf,
+
+        /// Source of the message (message, template, merge, squash or commit)
+        source: Option<String>,
+
+        /// Commit id, given for amends and -c/-C
+        sha: Option<String>,
+    },
+
     /// Show git commit log (changelog)
     Log {
         #[arg(
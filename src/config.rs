@@ -1,5 +1,6 @@
 use anyhow::{Context, Result};
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::fs;
 use std::path::PathBuf;
 
@@ -7,29 +8,188 @@ use std::path::PathBuf;
 pub struct Config {
     pub ai: AIConfig,
     pub commit: CommitConfig,
+    /// Rules checked on generated messages and by `lint`
+    #[serde(default)]
+    pub lint: LintConfig,
+    /// Providers tried in order when the primary `[ai]` provider fails
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub fallback: Vec<AIConfig>,
+    /// OpenAI-compatible gateways usable as `provider` by their name
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub gateways: BTreeMap<String, GatewayConfig>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct AIConfig {
     pub provider: String,
     pub model: String,
+    #[serde(default)]
     pub api_key_env: String,
     pub api_key: Option<String>,
     pub base_url: Option<String>,
     #[serde(default = "default_max_tokens")]
     pub max_tokens: u32,
+    /// Ollama only: how long the model stays loaded after a request
+    #[serde(default)]
+    pub keep_alive: Option<String>,
+    /// Azure only: deployment name (defaults to `model`)
+    #[serde(default)]
+    pub azure_deployment: Option<String>,
+    /// Azure only: `api-version` query parameter
+    #[serde(default)]
+    pub azure_api_version: Option<String>,
+    /// Stream tokens to the terminal while the commit message is generated
+    #[serde(default = "default_stream")]
+    pub stream: bool,
+    /// Retries for rate limits (429), server errors (5xx) and connection failures
+    #[serde(default = "default_max_retries")]
+    pub max_retries: u32,
+    /// Initial retry delay in milliseconds, doubled after every attempt
+    #[serde(default = "default_backoff_ms")]
+    pub backoff_ms: u64,
+}
+
+/// An OpenAI-compatible chat completions endpoint, e.g. an in-house gateway.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct GatewayConfig {
+    /// Endpoint below which `/chat/completions` is served
+    pub base_url: String,
+    /// Header carrying the API key as is (default: `Authorization: Bearer <key>`)
+    #[serde(default)]
+    pub auth_header: Option<String>,
+    /// Extra headers sent with every request
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
 }
 
 fn default_max_tokens() -> u32 {
     2000
 }
 
+fn default_stream() -> bool {
+    true
+}
+
+fn default_languages() -> Vec<String> {
+    crate::ai::language::DEFAULT_LANGUAGES
+        .iter()
+        .map(|s| s.to_string())
+        .collect()
+}
+
+fn default_staged_only() -> bool {
+    true
+}
+
+fn default_exclude() -> Vec<String> {
+    crate::git::DEFAULT_EXCLUDES
+        .iter()
+        .map(|s| s.to_string())
+        .collect()
+}
+
+fn default_max_concurrency() -> usize {
+    4
+}
+
+fn default_detect_scopes() -> bool {
+    true
+}
+
+fn default_lint_enabled() -> bool {
+    true
+}
+
+fn default_max_retries() -> u32 {
+    3
+}
+
+fn default_backoff_ms() -> u64 {
+    1000
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct CommitConfig {
     pub format: String,
     pub include_emoji: bool,
     pub max_diff_size: usize,
     pub auto_stage: bool,
+    /// Commit message languages, primary first (e.g. ["en"] or ["ja", "en"])
+    #[serde(default = "default_languages")]
+    pub languages: Vec<String>,
+    /// Diff size in bytes above which the message is generated map-reduce
+    /// style, one request per chunk plus a merge (0 = only with --map-reduce)
+    #[serde(default)]
+    pub map_reduce_threshold: usize,
+    /// Maximum number of concurrent chunk requests in map-reduce mode
+    #[serde(default = "default_max_concurrency")]
+    pub max_concurrency: usize,
+    /// Generate the message from staged changes only, i.e. exactly what gets
+    /// committed (false = also describe unstaged changes)
+    #[serde(default = "default_staged_only")]
+    pub staged_only: bool,
+    /// Globs of files summarized in one line instead of sent as a diff
+    #[serde(default = "default_exclude")]
+    pub exclude: Vec<String>,
+    /// Path globs mapped to scopes, e.g. `"src/ai/**" = "ai"`; the longest
+    /// matching pattern wins
+    #[serde(default)]
+    pub scope_map: BTreeMap<String, String>,
+    /// Without a scope map, detect scopes from Cargo or npm workspaces or
+    /// the top-level directories
+    #[serde(default = "default_detect_scopes")]
+    pub detect_scopes: bool,
+}
+
+/// Team rules for commit messages, see `crate::lint`.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct LintConfig {
+    /// Check generated messages against the rules below (the `lint` command
+    /// always checks them). Off without a `[lint]` section, on with one.
+    #[serde(default = "default_lint_enabled")]
+    pub enabled: bool,
+    /// Allowed commit types (empty = the types of `commit.format`)
+    pub types: Vec<String>,
+    /// Allowed scopes (empty = any scope)
+    pub scopes: Vec<String>,
+    /// Maximum length of each header line in characters (0 = unlimited)
+    pub max_header_length: usize,
+    /// Maximum length of each body line in characters (0 = unlimited)
+    pub max_body_line_length: usize,
+    /// English descriptions must use the imperative mood ("add", not "added")
+    pub imperative: bool,
+    /// Send violations back to the AI once to have them fixed
+    pub fix: bool,
+}
+
+impl Default for LintConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            types: Vec::new(),
+            scopes: Vec::new(),
+            max_header_length: 72,
+            max_body_line_length: 100,
+            imperative: true,
+            fix: true,
+        }
+    }
+}
+
+impl AIConfig {
+    pub fn get_api_key(&self) -> Option<String> {
+        // First check if api_key is directly set
+        if let Some(key) = &self.api_key {
+            return Some(key.clone());
+        }
+
+        // Then check environment variable
+        if self.api_key_env.is_empty() {
+            return None;
+        }
+        std::env::var(&self.api_key_env).ok()
+    }
 }
 
 impl Default for Config {
@@ -42,13 +202,29 @@ impl Default for Config {
                 api_key: None,
                 base_url: None,
                 max_tokens: 2000,
+                keep_alive: None,
+                azure_deployment: None,
+                azure_api_version: None,
+                stream: true,
+                max_retries: 3,
+                backoff_ms: 1000,
             },
             commit: CommitConfig {
                 format: "conventional".to_string(),
                 include_emoji: false,
                 max_diff_size: 10000,
                 auto_stage: false,
+                languages: default_languages(),
+                map_reduce_threshold: 0,
+                max_concurrency: default_max_concurrency(),
+                staged_only: true,
+                exclude: default_exclude(),
+                scope_map: BTreeMap::new(),
+                detect_scopes: true,
             },
+            lint: LintConfig::default(),
+            fallback: Vec::new(),
+            gateways: BTreeMap::new(),
         }
     }
 }
@@ -79,13 +255,26 @@ impl Config {
     }
 
     pub fn get_api_key(&self) -> Option<String> {
-        // First check if api_key is directly set
-        if let Some(key) = &self.ai.api_key {
-            return Some(key.clone());
-        }
+        self.ai.get_api_key()
+    }
 
-        // Then check environment variable
-        std::env::var(&self.ai.api_key_env).ok()
+    /// The `[ai]` provider followed by the `[[fallback]]` providers.
+    pub fn providers(&self) -> Vec<&AIConfig> {
+        std::iter::once(&self.ai).chain(&self.fallback).collect()
+    }
+
+    /// Copy of the config with provider `index` of `providers()` as `[ai]`;
+    /// the others stay as fallbacks in their original order.
+    pub fn with_primary(&self, index: usize) -> Config {
+        let mut providers: Vec<AIConfig> = self.providers().into_iter().cloned().collect();
+        let primary = providers.remove(index);
+        Config {
+            ai: primary,
+            commit: self.commit.clone(),
+            lint: self.lint.clone(),
+            fallback: providers,
+            gateways: self.gateways.clone(),
+        }
     }
 
     pub fn init(local: bool, force: bool) -> Result<PathBuf> {
@@ -111,17 +300,22 @@ impl Config {
 # This file configures the rust-git-cli tool for AI-powered commit message generation
 
 [ai]
-# AI provider: "openai" or "anthropic"
+# AI provider: "openai", "azure", "anthropic", "gemini", "ollama" or the
+# name of a [gateways.<name>] entry below
 provider = "openai"
 
 # Model to use for generation
 # OpenAI: "gpt-4", "gpt-4-turbo", "gpt-3.5-turbo"
 # Anthropic: "claude-3-opus", "claude-3-sonnet", "claude-3-haiku"
+# Gemini: "gemini-2.0-flash", "gemini-1.5-pro"
+# Ollama: any locally pulled model, e.g. "llama3.1", "qwen2.5-coder"
 model = "gpt-4"
 
 # Environment variable containing the API key
 # For OpenAI: typically "OPENAI_API_KEY"
 # For Anthropic: typically "ANTHROPIC_API_KEY"
+# For Gemini: typically "GEMINI_API_KEY"
+# Ollama does not need an API key
 api_key_env = "OPENAI_API_KEY"
 
 # Direct API key (not recommended for security reasons)
@@ -129,10 +323,16 @@ api_key_env = "OPENAI_API_KEY"
 # api_key = "your-api-key-here"
 
 # Custom API endpoint (optional - uncomment and modify if using a proxy or alternative API)
-# For OpenAI-compatible APIs (e.g., Azure OpenAI, local LLMs, proxies):
+# For OpenAI-compatible APIs (e.g., local LLMs, proxies):
 # base_url = "https://api.openai.com/v1"
 # For Anthropic-compatible APIs:
 # base_url = "https://api.anthropic.com"
+# For Azure OpenAI (provider = "azure"), the resource endpoint is required:
+# base_url = "https://your-resource.openai.azure.com"
+# For Gemini (generateContent API):
+# base_url = "https://generativelanguage.googleapis.com"
+# For Ollama (native /api/chat endpoint):
+# base_url = "http://localhost:11434"
 # Examples:
 # base_url = "https://your-proxy.com/v1"  # For API proxies
 # base_url = "http://localhost:8080/v1"   # For local LLMs
@@ -142,18 +342,125 @@ api_key_env = "OPENAI_API_KEY"
 # it will automatically double up to 4000 tokens.
 max_tokens = 2000
 
+# Stream the response and show tokens live while generating (OpenAI, Azure, Anthropic)
+# Set to false if your proxy doesn't support server-sent events
+stream = true
+
+# Retry rate limits (429), server errors (5xx) and connection failures.
+# A Retry-After header from the provider takes precedence over the backoff.
+max_retries = 3
+# Initial delay before retrying in milliseconds, doubled after every attempt
+backoff_ms = 1000
+
+# Ollama only: how long the model stays loaded after a request (default: "5m")
+# keep_alive = "30m"
+
+# Azure only: deployment name (defaults to `model`) and API version
+# azure_deployment = "gpt-4o-prod"
+# azure_api_version = "2024-06-01"
+
 [commit]
-# Commit message format: "conventional" (follows Conventional Commits spec)
+# Commit message format:
+#   "conventional"  type(scope): description (Conventional Commits)
+#   "gitmoji"       ✨ (scope): description
+#   "angular"       Conventional header with Angular's types and subject rules
+#   "plain"         summary line and body, no type prefix
+#   "kernel"        subsystem: summary (Linux kernel style)
 format = "conventional"
 
-# Whether to include emoji in commit messages
+# Whether to prefix the conventional header with an emoji for its type (e.g. "✨ feat: ...").
+# Ignored by the angular, plain and kernel formats
 include_emoji = false
 
-# Maximum diff size in characters to send to AI
+# Maximum diff size in bytes to send to AI (0 = unlimited). Larger diffs keep
+# every file header and the most informative hunks; the rest become stat lines.
 max_diff_size = 4000
 
-# Whether to automatically stage all changes before committing
+# Whether to stage all changes before committing without asking
 auto_stage = false
+
+# Describe only the staged changes, i.e. exactly what will be committed.
+# Set to false to also send unstaged changes to the AI (they are still not
+# committed unless staged).
+staged_only = true
+
+# Commit message languages, primary language first.
+# ["zh", "en"] (default) writes Chinese with English translations,
+# ["en"] writes English only, ["ja", "en"] writes Japanese + English.
+languages = ["zh", "en"]
+
+# Diffs larger than this many bytes are summarized in chunks (files grouped
+# by directory, each chunk up to max_diff_size) and then merged into one
+# message. 0 disables this unless `commit --map-reduce` is passed.
+map_reduce_threshold = 0
+
+# Maximum number of chunk requests sent at the same time
+max_concurrency = 4
+
+# Files that are only mentioned as "Cargo.lock: lockfile updated (N lines)"
+# instead of being sent as a diff. Patterns without "/" match in any
+# directory. Binary files and files marked `linguist-generated` or `-diff`
+# in .gitattributes are always summarized.
+exclude = [
+    "*.lock", "package-lock.json", "npm-shrinkwrap.json", "pnpm-lock.yaml", "go.sum",
+    "*.min.js", "*.min.css", "*.map", "*.snap", "__snapshots__/**",
+]
+
+# Without a scope map, scopes are detected from Cargo workspace members,
+# npm workspaces or (with at least two of them) the top-level directories.
+# Scopes from the map or from workspaces are enforced: the scope of the
+# changed files is used and others are dropped. Top-level directories are
+# only suggested to the AI.
+detect_scopes = true
+
+# Path globs mapped to scopes, replacing detection. The longest matching
+# pattern wins.
+# [commit.scope_map]
+# "src/ai/**" = "ai"
+# "src/git.rs" = "git"
+# "docs/**" = "docs"
+
+[lint]
+# Check generated messages against these rules. Violations are sent back to
+# the AI once to be fixed, and any that remain are shown before committing.
+# Off unless enabled here. `rust-git-cli lint` checks existing commits with
+# the same rules, even when `enabled = false`.
+enabled = false
+
+# Allowed commit types (empty = the types of commit.format)
+# types = ["feat", "fix", "docs", "refactor", "test", "chore"]
+
+# Allowed scopes (empty = any scope)
+# scopes = ["cli", "git", "ai"]
+
+# Maximum characters per header line and per body line (0 = unlimited)
+max_header_length = 72
+max_body_line_length = 100
+
+# English descriptions must use the imperative mood ("add", not "added")
+imperative = true
+
+# Ask the AI to fix violations before showing the message (the imperative
+# mood check is only reported). Each fix is one more request to the provider.
+fix = true
+
+# Fallback providers, tried in order when the [ai] provider fails.
+# Each entry accepts the same keys as [ai].
+# [[fallback]]
+# provider = "anthropic"
+# model = "claude-3-5-haiku-latest"
+# api_key_env = "ANTHROPIC_API_KEY"
+#
+# [[fallback]]
+# provider = "ollama"
+# model = "llama3.1"
+
+# OpenAI-compatible gateways, used with `provider = "<name>"`. The API key is
+# sent as a bearer token, or as is in `auth_header`.
+# [gateways.corp]
+# base_url = "https://llm-gateway.example.com/v1"
+# auth_header = "X-Api-Key"
+# headers = { "X-Team" = "platform" }
 "#;
 
         // Create parent directory if it doesn't exist
@@ -182,3 +489,23 @@ auto_stage = false
         Ok(path)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lint_only_enabled_with_lint_section() {
+        let base = "[ai]\nprovider = \"openai\"\nmodel = \"gpt-4\"\n\n[commit]\nformat = \"conventional\"\ninclude_emoji = false\nmax_diff_size = 4000\nauto_stage = false\n";
+
+        let config: Config = toml::from_str(base).unwrap();
+        assert!(!config.lint.enabled);
+        assert!(config.lint.fix);
+
+        let config: Config =
+            toml::from_str(&format!("{}\n[lint]\nimperative = false\n", base)).unwrap();
+        assert!(config.lint.enabled);
+        assert!(config.lint.fix);
+        assert!(!config.lint.imperative);
+    }
+}
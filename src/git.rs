@@ -1,5 +1,33 @@
+use crate::hooks;
 use anyhow::{Context, Result};
-use git2::{DiffOptions, Repository, StatusOptions};
+use git2::{
+    ApplyLocation, ApplyOptions, AttrCheckFlags, AttrValue, DiffOptions, IndexAddOption, Oid,
+    Repository, RepositoryState, StatusOptions,
+};
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use std::path::{Path, PathBuf};
+
+/// Files left out of the AI prompt by default (`commit.exclude`).
+pub const DEFAULT_EXCLUDES: &[&str] = &[
+    "*.lock",
+    "package-lock.json",
+    "npm-shrinkwrap.json",
+    "pnpm-lock.yaml",
+    "go.sum",
+    "*.min.js",
+    "*.min.css",
+    "*.map",
+    "*.snap",
+    "__snapshots__/**",
+];
+
+/// Lockfile names, used to describe excluded files.
+const LOCKFILES: &[&str] = &[
+    "package-lock.json",
+    "npm-shrinkwrap.json",
+    "pnpm-lock.yaml",
+    "go.sum",
+];
 
 pub struct GitRepo {
     repo: Repository,
@@ -25,11 +53,32 @@ impl GitRepo {
         let mut new_files = Vec::new();
         let mut deleted_files = Vec::new();
         let mut renamed_files = Vec::new();
+        let mut staged_files = Vec::new();
+        let mut unstaged_files = Vec::new();
 
         for entry in statuses.iter() {
             let status = entry.status();
             let path = entry.path().unwrap_or("unknown").to_string();
 
+            if status.intersects(
+                git2::Status::INDEX_NEW
+                    | git2::Status::INDEX_MODIFIED
+                    | git2::Status::INDEX_DELETED
+                    | git2::Status::INDEX_RENAMED
+                    | git2::Status::INDEX_TYPECHANGE,
+            ) {
+                staged_files.push(path.clone());
+            }
+            if status.intersects(
+                git2::Status::WT_NEW
+                    | git2::Status::WT_MODIFIED
+                    | git2::Status::WT_DELETED
+                    | git2::Status::WT_RENAMED
+                    | git2::Status::WT_TYPECHANGE,
+            ) {
+                unstaged_files.push(path.clone());
+            }
+
             if status.is_wt_modified() || status.is_index_modified() {
                 modified_files.push(path);
             } else if status.is_wt_new() || status.is_index_new() {
@@ -47,6 +96,8 @@ impl GitRepo {
             new_files,
             deleted_files,
             renamed_files,
+            staged_files,
+            unstaged_files,
         })
     }
 
@@ -100,7 +151,9 @@ impl GitRepo {
         }
     }
 
-    pub fn get_diff(&self, staged: bool) -> Result<String> {
+    /// Unified diff of staged (index vs HEAD) or unstaged changes. Files
+    /// matched by `filter` are replaced by a one-line summary at the top.
+    pub fn get_diff(&self, staged: bool, filter: Option<&DiffFilter>) -> Result<String> {
         let mut diff_opts = DiffOptions::new();
         diff_opts.include_untracked(true);
 
@@ -136,9 +189,77 @@ impl GitRepo {
                 .diff_index_to_workdir(None, Some(&mut diff_opts))?
         };
 
+        self.render_diff(&diff, filter)
+    }
+
+    /// Changes an amended HEAD would record: HEAD's parent (or nothing, for
+    /// a root commit) against the index, so newly staged changes are included.
+    pub fn get_amend_diff(&self, filter: Option<&DiffFilter>) -> Result<String> {
+        let head = self
+            .repo
+            .head()
+            .and_then(|head| head.peel_to_commit())
+            .context("There is no commit to amend")?;
+        let parent_tree = match head.parent(0) {
+            Ok(parent) => Some(parent.tree()?),
+            Err(_) => None,
+        };
+
+        let mut index = self.repo.index()?;
+        index.read(true)?;
+        let index_tree = self.repo.find_tree(index.write_tree()?)?;
+
+        let diff = self.repo.diff_tree_to_tree(
+            parent_tree.as_ref(),
+            Some(&index_tree),
+            Some(&mut DiffOptions::new()),
+        )?;
+        self.render_diff(&diff, filter)
+    }
+
+    /// Message of the HEAD commit.
+    pub fn head_message(&self) -> Result<String> {
+        let head = self
+            .repo
+            .head()
+            .and_then(|head| head.peel_to_commit())
+            .context("There is no commit to amend")?;
+        Ok(String::from_utf8_lossy(head.message_bytes()).into_owned())
+    }
+
+    /// Print `diff` as a unified diff. Files matched by `filter` are replaced
+    /// by a one-line summary at the top.
+    fn render_diff(&self, diff: &git2::Diff<'_>, filter: Option<&DiffFilter>) -> Result<String> {
         let mut diff_text = String::new();
-        diff.print(git2::DiffFormat::Patch, |_delta, _hunk, line| {
+        let mut excluded: Vec<(String, ExcludeReason, usize)> = Vec::new();
+        let mut current: Option<(PathBuf, bool)> = None;
+        diff.print(git2::DiffFormat::Patch, |delta, _hunk, line| {
             use git2::DiffLineType::*;
+
+            let path = delta
+                .new_file()
+                .path()
+                .or_else(|| delta.old_file().path())
+                .map(Path::to_path_buf)
+                .unwrap_or_default();
+            if current.as_ref().map(|(p, _)| p) != Some(&path) {
+                let reason =
+                    filter.and_then(|f| self.exclude_reason(&path, delta.flags().is_binary(), f));
+                if let Some(reason) = reason {
+                    excluded.push((path.display().to_string(), reason, 0));
+                }
+                current = Some((path, reason.is_some()));
+            }
+            if let Some((_, true)) = current {
+                // Only count the lines of excluded files
+                if matches!(line.origin_value(), Addition | Deletion) {
+                    if let Some((_, _, lines)) = excluded.last_mut() {
+                        *lines += 1;
+                    }
+                }
+                return true;
+            }
+
             let prefix = match line.origin_value() {
                 Addition => "+",
                 Deletion => "-",
@@ -150,12 +271,401 @@ impl GitRepo {
             true
         })?;
 
-        Ok(diff_text)
+        let summary: String = excluded
+            .iter()
+            .map(|(path, reason, lines)| reason.summarize(path, *lines))
+            .collect();
+        Ok(summary + &diff_text)
+    }
+
+    /// Why `path` should be left out of the prompt, if at all.
+    fn exclude_reason(
+        &self,
+        path: &Path,
+        binary: bool,
+        filter: &DiffFilter,
+    ) -> Option<ExcludeReason> {
+        if binary {
+            return Some(ExcludeReason::Binary);
+        }
+        if filter.globs.is_match(path) {
+            return Some(if is_lockfile(path) {
+                ExcludeReason::Lockfile
+            } else {
+                ExcludeReason::Generated
+            });
+        }
+
+        // .gitattributes: `linguist-generated` or `-diff`
+        let attr = |name| {
+            self.repo
+                .get_attr(path, name, AttrCheckFlags::FILE_THEN_INDEX)
+                .map(AttrValue::from_string)
+                .unwrap_or(AttrValue::Unspecified)
+        };
+        let generated = matches!(
+            attr("linguist-generated"),
+            AttrValue::True | AttrValue::String("true")
+        );
+        if generated || attr("diff") == AttrValue::False {
+            return Some(ExcludeReason::Generated);
+        }
+        None
+    }
+
+    /// Read the index from `path` instead of `.git/index`. Hooks get a
+    /// temporary index in `GIT_INDEX_FILE` for `git commit -a` or `git commit <paths>`.
+    pub fn use_index_file(&self, path: &Path) -> Result<()> {
+        let mut index = git2::Index::open(path)
+            .with_context(|| format!("Failed to open index file {:?}", path))?;
+        self.repo.set_index(&mut index)?;
+        Ok(())
+    }
+
+    /// Root of the working tree (`None` for bare repositories).
+    pub fn workdir(&self) -> Option<&Path> {
+        self.repo.workdir()
+    }
+
+    /// Paths of all files in the index.
+    pub fn tracked_files(&self) -> Result<Vec<String>> {
+        let index = self.repo.index()?;
+        Ok(index
+            .iter()
+            .map(|entry| String::from_utf8_lossy(&entry.path).into_owned())
+            .collect())
+    }
+
+    /// Write a `prepare-commit-msg` hook that runs `exe` in hook mode.
+    pub fn install_prepare_commit_msg_hook(&self, exe: &Path, force: bool) -> Result<PathBuf> {
+        hooks::install_prepare_commit_msg(&self.repo, exe, force)
+    }
+
+    /// Stage every change in the working tree, like `git add -A`.
+    pub fn stage_all(&self) -> Result<()> {
+        let mut index = self.repo.index()?;
+        index.read(true)?;
+        index
+            .add_all(["*"], IndexAddOption::DEFAULT, None)
+            .context("Failed to stage changes")?;
+        // add_all does not pick up deleted files
+        index
+            .update_all(["*"], None)
+            .context("Failed to stage deletions")?;
+        index.write().context("Failed to write index")?;
+        Ok(())
+    }
+
+    /// Reset the index to HEAD and keep the working tree, like `git reset`.
+    pub fn unstage_all(&self) -> Result<()> {
+        let mut index = self.repo.index()?;
+        index.read(true)?;
+        match self.repo.head() {
+            Ok(head) => index.read_tree(&head.peel_to_tree()?)?,
+            Err(e) if e.code() == git2::ErrorCode::UnbornBranch => index.clear()?,
+            Err(e) => return Err(e.into()),
+        }
+        index.write().context("Failed to write index")?;
+        Ok(())
+    }
+
+    /// Unstaged changes per file, including the content of untracked files,
+    /// for choosing what to stage. Hunk order matches `stage_hunks`. Files
+    /// excluded by `filter` keep their hunks but get a `summary`, which is
+    /// what the AI is shown instead.
+    pub fn get_unstaged_files(&self, filter: Option<&DiffFilter>) -> Result<Vec<FileDiff>> {
+        let diff = self.unstaged_diff(None)?;
+
+        let mut text = String::new();
+        let mut excluded: Vec<(String, ExcludeReason)> = Vec::new();
+        let mut current: Option<PathBuf> = None;
+        diff.print(git2::DiffFormat::Patch, |delta, _hunk, line| {
+            use git2::DiffLineType::*;
+
+            let path = delta
+                .new_file()
+                .path()
+                .or_else(|| delta.old_file().path())
+                .map(Path::to_path_buf)
+                .unwrap_or_default();
+            if current.as_ref() != Some(&path) {
+                let reason =
+                    filter.and_then(|f| self.exclude_reason(&path, delta.flags().is_binary(), f));
+                if let Some(reason) = reason {
+                    excluded.push((path.display().to_string(), reason));
+                }
+                current = Some(path);
+            }
+
+            let prefix = match line.origin_value() {
+                Addition => "+",
+                Deletion => "-",
+                Context => " ",
+                _ => "",
+            };
+            text.push_str(prefix);
+            text.push_str(&String::from_utf8_lossy(line.content()));
+            true
+        })?;
+
+        let mut files = parse_diff(&text);
+        for file in &mut files {
+            if let Some((_, reason)) = excluded.iter().find(|(path, _)| *path == file.path) {
+                file.summary = Some(reason.summarize(&file.path, file.added + file.removed));
+            }
+        }
+        Ok(files)
+    }
+
+    /// Stage the whole working tree version of `path`, like `git add <path>`.
+    pub fn stage_file(&self, path: &str) -> Result<()> {
+        let mut index = self.repo.index()?;
+        index.read(true)?;
+        let in_workdir = self
+            .repo
+            .workdir()
+            .map(|dir| dir.join(path).symlink_metadata().is_ok())
+            .unwrap_or(false);
+        if in_workdir {
+            index.add_path(Path::new(path))
+        } else {
+            index.remove_path(Path::new(path))
+        }
+        .with_context(|| format!("Failed to stage {}", path))?;
+        index.write().context("Failed to write index")?;
+        Ok(())
+    }
+
+    /// Stage only the hunks of `path` at the given positions in its unstaged
+    /// diff, like picking them in `git add -p`.
+    pub fn stage_hunks(&self, path: &str, hunks: &[usize]) -> Result<()> {
+        let diff = self.unstaged_diff(Some(path))?;
+
+        let mut position = 0;
+        let mut options = ApplyOptions::new();
+        options.hunk_callback(|_| {
+            let selected = hunks.contains(&position);
+            position += 1;
+            selected
+        });
+        self.repo
+            .apply(&diff, ApplyLocation::Index, Some(&mut options))
+            .with_context(|| format!("Failed to stage the selected hunks of {}", path))
+    }
+
+    /// Working tree vs index, optionally limited to one path.
+    fn unstaged_diff(&self, path: Option<&str>) -> Result<git2::Diff<'_>> {
+        let mut diff_opts = DiffOptions::new();
+        diff_opts
+            .include_untracked(true)
+            .recurse_untracked_dirs(true)
+            .show_untracked_content(true);
+        if let Some(path) = path {
+            diff_opts.pathspec(path).disable_pathspec_match(true);
+        }
+
+        let mut index = self.repo.index()?;
+        index.read(true)?;
+        self.repo
+            .diff_index_to_workdir(Some(&index), Some(&mut diff_opts))
+            .context("Failed to diff the working tree")
+    }
+
+    /// Run the `pre-commit` hook, failing with its output if it rejects the
+    /// commit. Run before generating a message, since it may restage files.
+    pub fn run_pre_commit_hook(&self) -> Result<()> {
+        match hooks::run_hook(&self.repo, "pre-commit", &[])? {
+            Some(hook) if !hook.success => {
+                anyhow::bail!("pre-commit hook failed:\n{}", hook.output)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Commit the index on top of HEAD. The message goes through the
+    /// `prepare-commit-msg` and `commit-msg` hooks first, which may rewrite
+    /// or reject it, like `git commit -m` does.
+    pub fn commit(&self, message: &str) -> Result<CommitOutcome> {
+        self.commit_with_hooks(message, false)
+    }
+
+    /// Replace HEAD with a commit of the index and `message`, keeping its
+    /// parents and author, like `git commit --amend -m`.
+    pub fn amend(&self, message: &str) -> Result<CommitOutcome> {
+        self.commit_with_hooks(message, true)
+    }
+
+    fn commit_with_hooks(&self, message: &str, amend: bool) -> Result<CommitOutcome> {
+        self.check_state(amend)?;
+
+        let message_path = self.repo.path().join("COMMIT_EDITMSG");
+        std::fs::write(&message_path, git2::message_prettify(message, None)?)
+            .context("Failed to write COMMIT_EDITMSG")?;
+        let path_arg = message_path.to_string_lossy();
+
+        // git passes the amended commit as the message source
+        let prepare_args: &[&str] = if amend {
+            &[&path_arg, "commit", "HEAD"]
+        } else {
+            &[&path_arg, "message"]
+        };
+        let message_hooks: [(&str, &[&str]); 2] = [
+            ("prepare-commit-msg", prepare_args),
+            ("commit-msg", &[&path_arg]),
+        ];
+        for (name, args) in message_hooks {
+            if let Some(hook) = hooks::run_hook(&self.repo, name, args)? {
+                if !hook.success {
+                    return Ok(CommitOutcome::Rejected {
+                        hook: name.to_string(),
+                        output: hook.output,
+                    });
+                }
+            }
+        }
+
+        let message = std::fs::read_to_string(&message_path)
+            .context("Failed to read COMMIT_EDITMSG after running hooks")?;
+        let id = if amend {
+            self.amend_head(&message)?
+        } else {
+            self.create_commit(&message)?
+        };
+
+        // The commit concludes a merge, cherry-pick or revert, like `git commit` does
+        if matches!(
+            self.repo.state(),
+            RepositoryState::Merge | RepositoryState::CherryPick | RepositoryState::Revert
+        ) {
+            self.repo
+                .cleanup_state()
+                .context("Failed to clean up the merge state")?;
+            // Without a todo list this was the last pick of the sequence
+            let sequencer = self.repo.path().join("sequencer");
+            if sequencer.exists() {
+                std::fs::remove_dir_all(&sequencer)
+                    .context("Failed to clean up the sequencer state")?;
+            }
+        }
+        Ok(CommitOutcome::Committed { id, message })
+    }
+
+    /// Refuse to commit in the middle of an operation the commit would
+    /// break. Merges, cherry-picks and reverts are concluded by a commit,
+    /// but not by an amend.
+    fn check_state(&self, amend: bool) -> Result<()> {
+        let operation = match self.repo.state() {
+            RepositoryState::Clean | RepositoryState::Bisect => return Ok(()),
+            RepositoryState::Merge | RepositoryState::CherryPick | RepositoryState::Revert
+                if !amend =>
+            {
+                return Ok(())
+            }
+            RepositoryState::Merge => "merge",
+            RepositoryState::CherryPick | RepositoryState::CherryPickSequence => "cherry-pick",
+            RepositoryState::Revert | RepositoryState::RevertSequence => "revert",
+            RepositoryState::ApplyMailbox | RepositoryState::ApplyMailboxOrRebase => "git am",
+            _ => "rebase",
+        };
+        anyhow::bail!(
+            "A {} is in progress. Finish or abort it with git first.",
+            operation
+        )
+    }
+
+    /// Commits being merged in (`MERGE_HEAD`), which become extra parents.
+    fn merge_heads(&self) -> Result<Vec<git2::Commit<'_>>> {
+        if self.repo.state() != RepositoryState::Merge {
+            return Ok(Vec::new());
+        }
+        let content = std::fs::read_to_string(self.repo.path().join("MERGE_HEAD"))
+            .context("Failed to read MERGE_HEAD")?;
+        content
+            .split_whitespace()
+            .map(|id| {
+                let commit = self.repo.find_commit(Oid::from_str(id)?)?;
+                Ok(commit)
+            })
+            .collect()
     }
 
-    pub fn get_combined_diff(&self) -> Result<String> {
-        let staged = self.get_diff(true)?;
-        let unstaged = self.get_diff(false)?;
+    /// Author of a cherry-picked commit being concluded, which keeps it like
+    /// `git commit` does. Reverts are authored by the committer.
+    fn picked_author(&self) -> Result<Option<git2::Signature<'static>>> {
+        if self.repo.state() != RepositoryState::CherryPick {
+            return Ok(None);
+        }
+        let content = std::fs::read_to_string(self.repo.path().join("CHERRY_PICK_HEAD"))
+            .context("Failed to read CHERRY_PICK_HEAD")?;
+        let picked = self.repo.find_commit(Oid::from_str(content.trim())?)?;
+        let author = picked.author().to_owned();
+        Ok(Some(author))
+    }
+
+    fn amend_head(&self, message: &str) -> Result<Oid> {
+        let mut index = self.repo.index()?;
+        index.read(true)?;
+        let tree = self.repo.find_tree(index.write_tree()?)?;
+
+        let head = self
+            .repo
+            .head()
+            .and_then(|head| head.peel_to_commit())
+            .context("There is no commit to amend")?;
+        let committer = self.signature()?;
+        let message = git2::message_prettify(message, None)?;
+
+        head.amend(
+            Some("HEAD"),
+            None,
+            Some(&committer),
+            None,
+            Some(&message),
+            Some(&tree),
+        )
+        .context("Failed to amend commit")
+    }
+
+    fn signature(&self) -> Result<git2::Signature<'static>> {
+        self.repo.signature().context(
+            "Git user not configured. Please run:\n  git config --global user.email \"you@example.com\"\n  git config --global user.name \"Your Name\"",
+        )
+    }
+
+    fn create_commit(&self, message: &str) -> Result<Oid> {
+        // Re-read the index: hooks or other tools may have changed it
+        let mut index = self.repo.index()?;
+        index.read(true)?;
+        let tree = self.repo.find_tree(index.write_tree()?)?;
+
+        let parent = match self.repo.head() {
+            Ok(head) => Some(head.peel_to_commit()?),
+            Err(e) if e.code() == git2::ErrorCode::UnbornBranch => None,
+            Err(e) => return Err(e.into()),
+        };
+        let merge_heads = self.merge_heads()?;
+        let unchanged = match &parent {
+            Some(parent) => parent.tree_id() == tree.id(),
+            None => tree.is_empty(),
+        };
+        // A merge may keep HEAD's tree, e.g. when the other side is already included
+        if unchanged && merge_heads.is_empty() {
+            anyhow::bail!("No changes to commit. All changes may already be committed.");
+        }
+
+        let committer = self.signature()?;
+        let author = self.picked_author()?.unwrap_or_else(|| committer.clone());
+        let message = git2::message_prettify(message, None)?;
+        let parents: Vec<&git2::Commit> = parent.iter().chain(&merge_heads).collect();
+
+        self.repo
+            .commit(Some("HEAD"), &author, &committer, &message, &tree, &parents)
+            .context("Failed to create commit")
+    }
+
+    pub fn get_combined_diff(&self, filter: Option<&DiffFilter>) -> Result<String> {
+        let staged = self.get_diff(true, filter)?;
+        let unstaged = self.get_diff(false, filter)?;
 
         let mut combined = String::new();
 
@@ -176,12 +686,74 @@ impl GitRepo {
     }
 }
 
+pub enum CommitOutcome {
+    /// Commit created with `message`, as left by the hooks
+    Committed { id: Oid, message: String },
+    /// A message hook exited non-zero; nothing was committed
+    Rejected { hook: String, output: String },
+}
+
+/// Exclude globs for files that should not be sent to the AI.
+pub struct DiffFilter {
+    globs: GlobSet,
+}
+
+impl DiffFilter {
+    /// Patterns without a `/` match the file name in any directory.
+    pub fn new(patterns: &[String]) -> Result<Self> {
+        let mut builder = GlobSetBuilder::new();
+        for pattern in patterns {
+            let glob = |p: &str| {
+                Glob::new(p).with_context(|| format!("Invalid exclude pattern: {}", pattern))
+            };
+            builder.add(glob(pattern)?);
+            if !pattern.contains('/') {
+                builder.add(glob(&format!("**/{}", pattern))?);
+            }
+        }
+        Ok(Self {
+            globs: builder
+                .build()
+                .context("Failed to build exclude patterns")?,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExcludeReason {
+    Lockfile,
+    Generated,
+    Binary,
+}
+
+impl ExcludeReason {
+    /// Line that stands in for the file's diff.
+    fn summarize(&self, path: &str, lines: usize) -> String {
+        match self {
+            ExcludeReason::Lockfile => format!("{}: lockfile updated ({} lines)\n", path, lines),
+            ExcludeReason::Generated => {
+                format!("{}: generated file updated ({} lines)\n", path, lines)
+            }
+            ExcludeReason::Binary => format!("{}: binary file updated\n", path),
+        }
+    }
+}
+
+fn is_lockfile(path: &Path) -> bool {
+    let name = path.file_name().and_then(|n| n.to_str()).unwrap_or("");
+    name.ends_with(".lock") || LOCKFILES.contains(&name)
+}
+
 pub struct GitStatus {
     pub is_clean: bool,
     pub modified_files: Vec<String>,
     pub new_files: Vec<String>,
     pub deleted_files: Vec<String>,
     pub renamed_files: Vec<String>,
+    /// Paths with changes in the index (what `git commit` would record)
+    pub staged_files: Vec<String>,
+    /// Paths with changes only in the working tree, including untracked files
+    pub unstaged_files: Vec<String>,
 }
 
 impl GitStatus {
@@ -219,6 +791,9 @@ pub struct CommitInfo {
 
 pub struct LogOptions {
     pub count: usize,
+    /// Revision range such as `origin/main..HEAD` or `main...HEAD`, or a
+    /// single revision to walk from (default: HEAD)
+    pub range: Option<String>,
     pub grep: Option<String>,
     pub author: Option<String>,
     pub since: Option<String>,
@@ -229,8 +804,33 @@ impl GitRepo {
     pub fn get_commits(&self, options: &LogOptions) -> Result<Vec<CommitInfo>> {
         use chrono::{Local, TimeZone};
 
+        let resolve = |rev: &str| {
+            let rev = if rev.is_empty() { "HEAD" } else { rev };
+            self.repo
+                .revparse_single(rev)
+                .and_then(|object| object.peel_to_commit())
+                .map(|commit| commit.id())
+                .with_context(|| format!("Unknown revision: {}", rev))
+        };
+
         let mut revwalk = self.repo.revwalk()?;
-        revwalk.push_head()?;
+        match options.range.as_deref() {
+            // Symmetric difference: commits on either side since they diverged
+            Some(range) if range.contains("...") => {
+                let (left, right) = range.split_once("...").unwrap_or_default();
+                let (left, right) = (resolve(left)?, resolve(right)?);
+                revwalk.push(left)?;
+                revwalk.push(right)?;
+                if let Ok(base) = self.repo.merge_base(left, right) {
+                    revwalk.hide(base)?;
+                }
+            }
+            Some(range) if range.contains("..") => revwalk
+                .push_range(range)
+                .with_context(|| format!("Invalid revision range: {}", range))?,
+            Some(rev) => revwalk.push(resolve(rev)?)?,
+            None => revwalk.push_head()?,
+        }
         revwalk.set_sorting(git2::Sort::TIME)?;
 
         let mut commits = Vec::new();
@@ -310,6 +910,92 @@ impl GitRepo {
     }
 }
 
+/// One file's section of a unified diff, as produced by `GitRepo::get_diff`.
+#[derive(Debug, Clone, Default)]
+pub struct FileDiff {
+    pub path: String,
+    /// `diff --git`, `index`, `---`/`+++` lines (and any section marker before them)
+    pub header: String,
+    /// Hunks, each starting with its `@@` line
+    pub hunks: Vec<String>,
+    pub added: usize,
+    pub removed: usize,
+    /// Line standing in for the hunks of a lockfile, generated or binary file
+    pub summary: Option<String>,
+}
+
+impl FileDiff {
+    /// Size of the section in bytes.
+    pub fn size(&self) -> usize {
+        self.header.len() + self.hunks.iter().map(String::len).sum::<usize>()
+    }
+}
+
+/// Split a unified diff into per-file sections. Lines that are not part of any
+/// file (e.g. the `=== STAGED CHANGES ===` markers of `get_combined_diff`) are
+/// kept in the header of the following file.
+pub fn parse_diff(diff: &str) -> Vec<FileDiff> {
+    let mut files: Vec<FileDiff> = Vec::new();
+    let mut pending = String::new();
+    let mut in_hunk = false;
+
+    for line in diff.split_inclusive('\n') {
+        if line.starts_with("diff --git ") {
+            let path = line
+                .trim_end()
+                .rsplit_once(" b/")
+                .map(|(_, path)| path.to_string())
+                .unwrap_or_default();
+            files.push(FileDiff {
+                path,
+                header: std::mem::take(&mut pending) + line,
+                ..Default::default()
+            });
+            in_hunk = false;
+            continue;
+        }
+
+        let Some(file) = files.last_mut() else {
+            pending.push_str(line);
+            continue;
+        };
+
+        if line.starts_with("=== ") && line.trim_end().ends_with(" ===") {
+            pending.push_str(line);
+        } else if line.starts_with("@@") {
+            file.hunks.push(line.to_string());
+            in_hunk = true;
+        } else if in_hunk {
+            if line.starts_with('+') {
+                file.added += 1;
+            } else if line.starts_with('-') {
+                file.removed += 1;
+            }
+            if let Some(hunk) = file.hunks.last_mut() {
+                hunk.push_str(line);
+            }
+        } else {
+            file.header.push_str(line);
+        }
+    }
+
+    // Anything left over belongs to no file; keep it so nothing is silently lost
+    if !pending.is_empty() {
+        match files.last_mut() {
+            Some(file) => match file.hunks.last_mut() {
+                Some(hunk) => hunk.push_str(&pending),
+                None => file.header.push_str(&pending),
+            },
+            None => files.push(FileDiff {
+                header: pending,
+                ..Default::default()
+            }),
+        }
+    }
+
+    files
+}
+
 fn parse_date_string(date_str: &str) -> Result<chrono::DateTime<chrono::Local>> {
     use chrono::{Local, NaiveDate};
 
@@ -337,3 +1023,360 @@ fn parse_date_string(date_str: &str) -> Result<chrono::DateTime<chrono::Local>>
 
     Err(anyhow::anyhow!("Could not parse date: {}", date_str))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_diff_splits_files_and_hunks() {
+        let diff = "=== STAGED CHANGES ===\n\ndiff --git a/src/a.rs b/src/a.rs\nindex 1..2 100644\n--- a/src/a.rs\n+++ b/src/a.rs\n@@ -1 +1 @@\n-old\n+new\n@@ -9 +9,2 @@\n ctx\n+more\ndiff --git a/b.txt b/b.txt\nBinary files differ\n";
+        let files = parse_diff(diff);
+
+        assert_eq!(files.len(), 2);
+        assert_eq!(files[0].path, "src/a.rs");
+        assert!(files[0].header.starts_with("=== STAGED CHANGES ==="));
+        assert!(files[0].header.ends_with("+++ b/src/a.rs\n"));
+        assert_eq!(files[0].hunks.len(), 2);
+        assert_eq!((files[0].added, files[0].removed), (2, 1));
+        assert_eq!(files[1].path, "b.txt");
+        assert!(files[1].hunks.is_empty());
+        assert_eq!(files.iter().map(FileDiff::size).sum::<usize>(), diff.len());
+    }
+
+    #[test]
+    fn test_get_diff_summarizes_excluded_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = Repository::init(dir.path()).unwrap();
+        let write = |path: &str, content: &str| {
+            let path = dir.path().join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, content).unwrap();
+        };
+        write(".gitattributes", "gen/** linguist-generated\n");
+        write("Cargo.lock", "a\nb\nc\n");
+        write("gen/schema.rs", "pub struct Generated;\n");
+        write("logo.png", "\0\x01binary");
+        write("src/main.rs", "fn main() {}\n");
+        let mut index = repo.index().unwrap();
+        index
+            .add_all(["*"], git2::IndexAddOption::DEFAULT, None)
+            .unwrap();
+        index.write().unwrap();
+
+        let git = GitRepo { repo };
+        let filter = DiffFilter::new(&["*.lock".to_string()]).unwrap();
+        let diff = git.get_diff(true, Some(&filter)).unwrap();
+
+        assert!(diff.contains("Cargo.lock: lockfile updated (3 lines)\n"));
+        assert!(diff.contains("gen/schema.rs: generated file updated (1 lines)\n"));
+        assert!(diff.contains("logo.png: binary file updated\n"));
+        assert!(diff.contains("+fn main() {}"));
+        assert!(!diff.contains("pub struct Generated"));
+        assert_eq!(parse_diff(&diff).len(), 2);
+    }
+
+    #[test]
+    fn test_status_separates_staged_and_unstaged_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = Repository::init(dir.path()).unwrap();
+        std::fs::write(dir.path().join("staged.txt"), "a\n").unwrap();
+        std::fs::write(dir.path().join("untracked.txt"), "b\n").unwrap();
+        let mut index = repo.index().unwrap();
+        index.add_path(Path::new("staged.txt")).unwrap();
+        index.write().unwrap();
+
+        let status = GitRepo { repo }.get_status().unwrap();
+        assert_eq!(status.staged_files, vec!["staged.txt"]);
+        assert_eq!(status.unstaged_files, vec!["untracked.txt"]);
+    }
+
+    fn committed(outcome: CommitOutcome) -> Oid {
+        match outcome {
+            CommitOutcome::Committed { id, .. } => id,
+            CommitOutcome::Rejected { hook, output } => panic!("{} rejected: {}", hook, output),
+        }
+    }
+
+    /// Empty repository with a committer identity configured.
+    fn temp_repo() -> (tempfile::TempDir, GitRepo) {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = Repository::init(dir.path()).unwrap();
+        let mut config = repo.config().unwrap();
+        config.set_str("user.name", "Test").unwrap();
+        config.set_str("user.email", "test@example.com").unwrap();
+        (dir, GitRepo { repo })
+    }
+
+    #[test]
+    fn test_stage_all_and_commit() {
+        let (dir, git) = temp_repo();
+        std::fs::write(dir.path().join("a.txt"), "a\n").unwrap();
+        git.stage_all().unwrap();
+        let first = committed(git.commit("feat: add a").unwrap());
+
+        std::fs::remove_file(dir.path().join("a.txt")).unwrap();
+        std::fs::write(dir.path().join("b.txt"), "b\n").unwrap();
+        git.stage_all().unwrap();
+        let second = committed(git.commit("fix: replace a with b").unwrap());
+
+        let commit = git.repo.find_commit(second).unwrap();
+        assert_eq!(commit.message(), Some("fix: replace a with b\n"));
+        assert_eq!(commit.parent_id(0).unwrap(), first);
+        let tree = commit.tree().unwrap();
+        assert!(tree.get_name("a.txt").is_none());
+        assert!(tree.get_name("b.txt").is_some());
+
+        let err = git.commit("chore: nothing").err().unwrap();
+        assert!(err.to_string().contains("No changes to commit"));
+    }
+
+    #[test]
+    fn test_stage_selected_hunks() {
+        let (dir, git) = temp_repo();
+        let lines: Vec<String> = (1..=20).map(|i| format!("line {}\n", i)).collect();
+        std::fs::write(dir.path().join("a.txt"), lines.concat()).unwrap();
+        git.stage_all().unwrap();
+        committed(git.commit("feat: add a").unwrap());
+
+        let mut changed = lines.clone();
+        changed[0] = "first\n".to_string();
+        changed[19] = "last\n".to_string();
+        std::fs::write(dir.path().join("a.txt"), changed.concat()).unwrap();
+        std::fs::write(dir.path().join("new.txt"), "new\n").unwrap();
+
+        let files = git.get_unstaged_files(None).unwrap();
+        let paths: Vec<&str> = files.iter().map(|f| f.path.as_str()).collect();
+        assert_eq!(paths, vec!["a.txt", "new.txt"]);
+        assert_eq!(files[0].hunks.len(), 2);
+
+        git.stage_hunks("a.txt", &[1]).unwrap();
+        git.stage_file("new.txt").unwrap();
+
+        let staged = git.get_diff(true, None).unwrap();
+        assert!(staged.contains("+last"));
+        assert!(!staged.contains("+first"));
+        assert!(staged.contains("+new"));
+        let unstaged = git.get_diff(false, None).unwrap();
+        assert!(unstaged.contains("+first"));
+        assert!(!unstaged.contains("+last"));
+    }
+
+    #[test]
+    fn test_unstaged_files_summarize_excluded_files() {
+        let (dir, git) = temp_repo();
+        std::fs::write(dir.path().join("a.txt"), "a\n").unwrap();
+        std::fs::write(dir.path().join("Cargo.lock"), "lock\n").unwrap();
+
+        let filter = DiffFilter::new(&["Cargo.lock".to_string()]).unwrap();
+        let files = git.get_unstaged_files(Some(&filter)).unwrap();
+        let lock = files.iter().find(|f| f.path == "Cargo.lock").unwrap();
+        assert_eq!(
+            lock.summary.as_deref(),
+            Some("Cargo.lock: lockfile updated (1 lines)\n")
+        );
+        assert_eq!(lock.hunks.len(), 1);
+        let a = files.iter().find(|f| f.path == "a.txt").unwrap();
+        assert!(a.summary.is_none());
+    }
+
+    #[test]
+    fn test_amend_rewrites_head() {
+        let (dir, git) = temp_repo();
+        std::fs::write(dir.path().join("a.txt"), "a\n").unwrap();
+        git.stage_all().unwrap();
+        let first = committed(git.commit("feat: add a").unwrap());
+        std::fs::write(dir.path().join("b.txt"), "b\n").unwrap();
+        git.stage_all().unwrap();
+        committed(git.commit("wip").unwrap());
+
+        std::fs::write(dir.path().join("c.txt"), "c\n").unwrap();
+        git.stage_file("c.txt").unwrap();
+        let diff = git.get_amend_diff(None).unwrap();
+        assert!(diff.contains("+b") && diff.contains("+c"));
+        assert!(!diff.contains("+a"));
+        assert_eq!(git.head_message().unwrap(), "wip\n");
+
+        let amended = committed(git.amend("feat: add b and c").unwrap());
+        let commit = git.repo.find_commit(amended).unwrap();
+        assert_eq!(commit.message(), Some("feat: add b and c\n"));
+        assert_eq!(commit.parent_id(0).unwrap(), first);
+        assert!(commit.tree().unwrap().get_name("c.txt").is_some());
+    }
+
+    #[test]
+    fn test_get_commits_in_range() {
+        let (dir, git) = temp_repo();
+        let mut ids = Vec::new();
+        for name in ["a", "b", "c"] {
+            std::fs::write(dir.path().join(name), name).unwrap();
+            git.stage_all().unwrap();
+            ids.push(committed(
+                git.commit(&format!("feat: add {}", name)).unwrap(),
+            ));
+        }
+        let options = |count: usize, range: &str| LogOptions {
+            count,
+            range: Some(range.to_string()),
+            grep: None,
+            author: None,
+            since: None,
+            until: None,
+        };
+
+        // The commits share a timestamp, so compare them in a fixed order
+        let summaries = |commits: Vec<CommitInfo>| -> Vec<String> {
+            let mut summaries: Vec<String> = commits.into_iter().map(|c| c.summary).collect();
+            summaries.sort();
+            summaries
+        };
+        let range = format!("{}..HEAD", ids[0]);
+        assert_eq!(
+            summaries(git.get_commits(&options(usize::MAX, &range)).unwrap()),
+            vec!["feat: add b", "feat: add c"]
+        );
+        assert_eq!(
+            summaries(git.get_commits(&options(1, "HEAD~1")).unwrap()),
+            vec!["feat: add b"]
+        );
+        assert!(git.get_commits(&options(1, "nope")).is_err());
+
+        // A side branch from `a`: `b...` covers both sides since they diverged
+        git.repo
+            .branch("side", &git.repo.find_commit(ids[0]).unwrap(), false)
+            .unwrap();
+        git.repo.set_head("refs/heads/side").unwrap();
+        git.repo
+            .checkout_head(Some(git2::build::CheckoutBuilder::new().force()))
+            .unwrap();
+        std::fs::write(dir.path().join("d"), "d").unwrap();
+        git.stage_all().unwrap();
+        committed(git.commit("feat: add d").unwrap());
+        let range = format!("{}...", ids[1]);
+        assert_eq!(
+            summaries(git.get_commits(&options(usize::MAX, &range)).unwrap()),
+            vec!["feat: add b", "feat: add d"]
+        );
+        assert!(git.get_commits(&options(1, "nope...HEAD")).is_err());
+    }
+
+    #[test]
+    fn test_commit_concludes_merge() {
+        let (dir, git) = temp_repo();
+        std::fs::write(dir.path().join("a.txt"), "a\n").unwrap();
+        git.stage_all().unwrap();
+        let base = committed(git.commit("feat: add a").unwrap());
+        std::fs::write(dir.path().join("b.txt"), "b\n").unwrap();
+        git.stage_all().unwrap();
+        let other = committed(git.commit("feat: add b").unwrap());
+
+        // Go back to the base and diverge, then merge the other line in
+        let base_commit = git.repo.find_commit(base).unwrap();
+        git.repo
+            .reset(base_commit.as_object(), git2::ResetType::Hard, None)
+            .unwrap();
+        std::fs::write(dir.path().join("c.txt"), "c\n").unwrap();
+        git.stage_all().unwrap();
+        let ours = committed(git.commit("feat: add c").unwrap());
+        let theirs = git.repo.find_annotated_commit(other).unwrap();
+        git.repo.merge(&[&theirs], None, None).unwrap();
+        assert_eq!(git.repo.state(), RepositoryState::Merge);
+        assert!(git.amend("feat: add c").is_err());
+
+        let merge = committed(git.commit("Merge feature b").unwrap());
+        let commit = git.repo.find_commit(merge).unwrap();
+        assert_eq!(commit.parent_ids().collect::<Vec<_>>(), vec![ours, other]);
+        assert_eq!(git.repo.state(), RepositoryState::Clean);
+        assert!(!git.repo.path().join("MERGE_HEAD").exists());
+    }
+
+    #[test]
+    fn test_commit_concludes_cherry_pick_with_its_author() {
+        let (dir, git) = temp_repo();
+        std::fs::write(dir.path().join("a.txt"), "a\n").unwrap();
+        git.stage_all().unwrap();
+        let base = committed(git.commit("feat: add a").unwrap());
+
+        // A commit by someone else on top of the base
+        std::fs::write(dir.path().join("b.txt"), "b\n").unwrap();
+        git.stage_all().unwrap();
+        let tree = git
+            .repo
+            .find_tree(git.repo.index().unwrap().write_tree().unwrap())
+            .unwrap();
+        let other = git2::Signature::now("Other", "other@example.com").unwrap();
+        let base_commit = git.repo.find_commit(base).unwrap();
+        let picked = git
+            .repo
+            .commit(None, &other, &other, "feat: add b", &tree, &[&base_commit])
+            .unwrap();
+
+        git.repo
+            .reset(base_commit.as_object(), git2::ResetType::Hard, None)
+            .unwrap();
+        git.repo
+            .cherrypick(&git.repo.find_commit(picked).unwrap(), None)
+            .unwrap();
+        std::fs::create_dir(git.repo.path().join("sequencer")).unwrap();
+        assert_eq!(git.repo.state(), RepositoryState::CherryPick);
+
+        let id = committed(git.commit("feat: add b").unwrap());
+        let commit = git.repo.find_commit(id).unwrap();
+        assert_eq!(commit.author().name(), Some("Other"));
+        assert_eq!(commit.committer().name(), Some("Test"));
+        assert_eq!(commit.parent_ids().collect::<Vec<_>>(), vec![base]);
+        assert_eq!(git.repo.state(), RepositoryState::Clean);
+        assert!(!git.repo.path().join("sequencer").exists());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_commit_runs_hooks() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let (dir, git) = temp_repo();
+        let hook = |name: &str, script: &str| {
+            let path = git.repo.path().join("hooks").join(name);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(&path, script).unwrap();
+            std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+        };
+        hook(
+            "commit-msg",
+            "#!/bin/sh\nprintf '\\nSigned-off-by: Test <test@example.com>\\n' >> \"$1\"\n",
+        );
+        std::fs::write(dir.path().join("a.txt"), "a\n").unwrap();
+        git.stage_all().unwrap();
+
+        let id = committed(git.commit("feat: add a").unwrap());
+        let message = git
+            .repo
+            .find_commit(id)
+            .unwrap()
+            .message()
+            .unwrap()
+            .to_string();
+        assert_eq!(
+            message,
+            "feat: add a\n\nSigned-off-by: Test <test@example.com>\n"
+        );
+
+        hook(
+            "commit-msg",
+            "#!/bin/sh\necho 'missing ticket id'\nexit 1\n",
+        );
+        std::fs::write(dir.path().join("b.txt"), "b\n").unwrap();
+        git.stage_all().unwrap();
+        match git.commit("feat: add b").unwrap() {
+            CommitOutcome::Rejected { hook, output } => {
+                assert_eq!(hook, "commit-msg");
+                assert_eq!(output, "missing ticket id");
+            }
+            CommitOutcome::Committed { .. } => panic!("commit-msg hook was not run"),
+        }
+
+        hook("pre-commit", "#!/bin/sh\necho 'lint failed'\nexit 1\n");
+        let err = git.run_pre_commit_hook().unwrap_err();
+        assert!(err.to_string().contains("lint failed"));
+    }
+}
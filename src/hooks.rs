@@ -0,0 +1,188 @@
+//! Runs the repository's git hooks. Commits are created with libgit2, which
+//! never runs hooks on its own, so `GitRepo` calls these explicitly.
+
+use anyhow::{Context, Result};
+use git2::Repository;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+pub struct HookOutput {
+    pub success: bool,
+    /// Combined stdout and stderr of the hook
+    pub output: String,
+}
+
+/// Directory hooks are read from: `core.hooksPath` or `.git/hooks`.
+pub fn hooks_dir(repo: &Repository) -> PathBuf {
+    let configured = repo
+        .config()
+        .and_then(|config| config.get_path("core.hooksPath"))
+        .ok();
+
+    match configured {
+        Some(path) if path.is_absolute() => path,
+        // Relative paths are relative to the working tree, like git does
+        Some(path) => repo.workdir().unwrap_or_else(|| repo.path()).join(path),
+        None => repo.path().join("hooks"),
+    }
+}
+
+/// Run hook `name` with `args`. Returns `None` when the hook does not exist
+/// or is not executable.
+pub fn run_hook(repo: &Repository, name: &str, args: &[&str]) -> Result<Option<HookOutput>> {
+    let path = hooks_dir(repo).join(name);
+    if !is_executable(&path) {
+        return Ok(None);
+    }
+
+    let mut command = Command::new(&path);
+    command
+        .args(args)
+        .current_dir(repo.workdir().unwrap_or_else(|| repo.path()));
+    // An index the caller set (e.g. the temporary one of `git commit -a`) is inherited
+    if std::env::var_os("GIT_INDEX_FILE").is_none() {
+        command.env("GIT_INDEX_FILE", index_path(repo));
+    }
+    let output = command
+        .output()
+        .with_context(|| format!("Failed to run {} hook", name))?;
+
+    let mut text = String::from_utf8_lossy(&output.stdout).into_owned();
+    text.push_str(&String::from_utf8_lossy(&output.stderr));
+
+    Ok(Some(HookOutput {
+        success: output.status.success(),
+        output: text.trim().to_string(),
+    }))
+}
+
+/// Path of the index `repo` stages into, e.g. one set by
+/// `GitRepo::use_index_file`, or `.git/index`.
+fn index_path(repo: &Repository) -> PathBuf {
+    repo.index()
+        .ok()
+        .and_then(|index| index.path().map(Path::to_path_buf))
+        .unwrap_or_else(|| repo.path().join("index"))
+}
+
+/// Marks hooks written by `install-hook`, so they can be replaced safely.
+const INSTALLED_MARKER: &str = "# Installed by rust-git-cli";
+
+/// Write a `prepare-commit-msg` hook that runs `exe prepare-commit-msg`.
+/// An existing hook not written by us is only replaced with `force`.
+pub fn install_prepare_commit_msg(repo: &Repository, exe: &Path, force: bool) -> Result<PathBuf> {
+    let dir = hooks_dir(repo);
+    let path = dir.join("prepare-commit-msg");
+
+    if path.exists() && !force {
+        let existing = std::fs::read_to_string(&path).unwrap_or_default();
+        if !existing.contains(INSTALLED_MARKER) {
+            anyhow::bail!(
+                "A prepare-commit-msg hook already exists at {}. Use --force to replace it.",
+                path.display()
+            );
+        }
+    }
+
+    let script = format!(
+        "#!/bin/sh\n{}: fill in an AI generated commit message\nexec {} prepare-commit-msg \"$@\"\n",
+        INSTALLED_MARKER,
+        shell_quote(&exe.display().to_string())
+    );
+    std::fs::create_dir_all(&dir)
+        .with_context(|| format!("Failed to create hooks directory {:?}", dir))?;
+    std::fs::write(&path, script).with_context(|| format!("Failed to write {:?}", path))?;
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755))
+            .with_context(|| format!("Failed to make {:?} executable", path))?;
+    }
+
+    Ok(path)
+}
+
+/// Single-quote `value` for sh, so `$`, backticks and quotes in it stay literal.
+fn shell_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', r"'\''"))
+}
+
+/// Whether `prepare-commit-msg` should leave the message alone for this
+/// commit source: `-m`/`-F` messages, merges, squashes and amends (`commit`).
+pub fn keeps_existing_message(source: Option<&str>) -> bool {
+    matches!(source, Some("message" | "merge" | "squash" | "commit"))
+}
+
+#[cfg(unix)]
+fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+
+    path.metadata()
+        .map(|m| m.is_file() && m.permissions().mode() & 0o111 != 0)
+        .unwrap_or(false)
+}
+
+#[cfg(not(unix))]
+fn is_executable(path: &Path) -> bool {
+    path.is_file()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_install_prepare_commit_msg_respects_foreign_hooks() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = Repository::init(dir.path()).unwrap();
+        let exe = Path::new("/usr/local/bin/rust-git-cli");
+
+        let path = install_prepare_commit_msg(&repo, exe, false).unwrap();
+        let script = std::fs::read_to_string(&path).unwrap();
+        assert!(script.contains("exec '/usr/local/bin/rust-git-cli' prepare-commit-msg \"$@\""));
+        assert!(is_executable(&path));
+        // Our own hook can be reinstalled
+        install_prepare_commit_msg(&repo, exe, false).unwrap();
+
+        std::fs::write(&path, "#!/bin/sh\necho custom\n").unwrap();
+        assert!(install_prepare_commit_msg(&repo, exe, false).is_err());
+        install_prepare_commit_msg(&repo, exe, true).unwrap();
+
+        assert_eq!(
+            shell_quote("/opt/it's $HOME/`x`"),
+            r"'/opt/it'\''s $HOME/`x`'"
+        );
+
+        assert!(keeps_existing_message(Some("merge")));
+        assert!(!keeps_existing_message(Some("template")));
+        assert!(!keeps_existing_message(None));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_run_hook_uses_index_in_use() {
+        use std::os::unix::fs::PermissionsExt;
+
+        // Only meaningful when the test itself does not run inside a hook
+        if std::env::var_os("GIT_INDEX_FILE").is_some() {
+            return;
+        }
+
+        let dir = tempfile::tempdir().unwrap();
+        let repo = Repository::init(dir.path()).unwrap();
+        let hook = hooks_dir(&repo).join("pre-commit");
+        std::fs::create_dir_all(hook.parent().unwrap()).unwrap();
+        std::fs::write(&hook, "#!/bin/sh\nprintf %s \"$GIT_INDEX_FILE\"\n").unwrap();
+        std::fs::set_permissions(&hook, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        let output = run_hook(&repo, "pre-commit", &[]).unwrap().unwrap();
+        assert_eq!(Path::new(&output.output), repo.path().join("index"));
+
+        let temporary = dir.path().join("next-index");
+        repo.set_index(&mut git2::Index::open(&temporary).unwrap())
+            .unwrap();
+        let output = run_hook(&repo, "pre-commit", &[]).unwrap().unwrap();
+        assert_eq!(Path::new(&output.output), temporary);
+    }
+}
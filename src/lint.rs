@@ -0,0 +1,561 @@
+//! Team rules for commit messages (`[lint]` in the config).
+//!
+//! Generated messages are checked as a `CommitMessage` plus its rendered
+//! text; existing commits only have text, so their type and scope are
+//! parsed back out of the header according to `commit.format`.
+
+use crate::ai::{CommitContext, CommitMessage, MessageStyle, PromptStage, ProviderChain};
+use crate::config::LintConfig;
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::fmt;
+use std::path::Path;
+
+/// One broken rule. Rule names follow commitlint where one exists.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Violation {
+    pub rule: &'static str,
+    pub message: String,
+}
+
+impl Violation {
+    fn new(rule: &'static str, message: String) -> Self {
+        Self { rule, message }
+    }
+
+    /// Whether the AI should be asked to fix it. The imperative mood check
+    /// is a word-list guess, so it is only reported.
+    pub fn fixable(&self) -> bool {
+        self.rule != "subject-mood"
+    }
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} [{}]", self.message, self.rule)
+    }
+}
+
+/// Violations of one checked message.
+#[derive(Debug, Serialize)]
+pub struct Report {
+    /// Commit id, or the path of the message file
+    pub source: String,
+    pub summary: String,
+    pub violations: Vec<Violation>,
+}
+
+/// Type, scope and description lines of a message header.
+#[derive(Debug, Default)]
+struct Header {
+    commit_type: Option<String>,
+    scope: Option<String>,
+    /// Description of the first line and the translations below it
+    subjects: Vec<String>,
+}
+
+/// Past tense forms that are also imperatives.
+const NOT_PAST_TENSE: &[&str] = &[
+    "bleed", "breed", "embed", "exceed", "feed", "need", "proceed", "seed", "shed", "speed",
+    "succeed",
+];
+
+/// `-ing` words that are also imperatives.
+const NOT_GERUND: &[&str] = &["bring", "ping", "ring", "sing", "string"];
+
+/// Third person forms of verbs that commonly start a description.
+const THIRD_PERSON: &[&str] = &[
+    "adds",
+    "allows",
+    "bumps",
+    "changes",
+    "cleans",
+    "converts",
+    "creates",
+    "deletes",
+    "disables",
+    "enables",
+    "ensures",
+    "fixes",
+    "handles",
+    "implements",
+    "improves",
+    "introduces",
+    "makes",
+    "moves",
+    "prevents",
+    "refactors",
+    "removes",
+    "renames",
+    "replaces",
+    "simplifies",
+    "supports",
+    "updates",
+    "uses",
+];
+
+/// Checks messages against the configured rules for one message style.
+pub struct Linter<'a> {
+    config: &'a LintConfig,
+    style: MessageStyle,
+}
+
+impl<'a> Linter<'a> {
+    pub fn new(config: &'a LintConfig, style: MessageStyle) -> Self {
+        Self { config, style }
+    }
+
+    /// Configured types, or the types of the message style.
+    pub fn allowed_types(&self) -> Vec<String> {
+        if self.config.types.is_empty() {
+            self.style
+                .allowed_types()
+                .iter()
+                .map(|t| t.to_string())
+                .collect()
+        } else {
+            self.config.types.clone()
+        }
+    }
+
+    /// The rules as prompt requirements, so the first attempt already
+    /// follows them.
+    pub fn prompt_rules(&self) -> Vec<String> {
+        if !self.config.enabled {
+            return Vec::new();
+        }
+
+        let mut rules = Vec::new();
+        if !self.config.types.is_empty() {
+            rules.push(format!(
+                "type must be one of: {}",
+                self.config.types.join(", ")
+            ));
+        }
+        if !self.config.scopes.is_empty() {
+            rules.push(format!(
+                "scope must be one of: {} (or null if none fits)",
+                self.config.scopes.join(", ")
+            ));
+        }
+        if self.config.max_header_length > 0 {
+            rules.push(format!(
+                "Each header line including the type and scope prefix must be at most {} characters",
+                self.config.max_header_length
+            ));
+        }
+        if self.config.imperative {
+            rules.push(
+                "Write English descriptions in the imperative mood (\"add\", not \"added\" or \"adds\")"
+                    .to_string(),
+            );
+        }
+        if self.config.max_body_line_length > 0 {
+            rules.push(format!(
+                "Each body point must be at most {} characters",
+                self.config.max_body_line_length
+            ));
+        }
+        rules
+    }
+
+    /// Check a generated message; `rendered` is its text in this style.
+    pub fn lint_message(&self, message: &CommitMessage, rendered: &str) -> Vec<Violation> {
+        let header = Header {
+            commit_type: self
+                .shows_type()
+                .then(|| message.commit_type.clone())
+                .filter(|t| !t.is_empty()),
+            scope: message.scope.clone().filter(|s| !s.is_empty()),
+            subjects: self
+                .parse_header(rendered)
+                .map(|h| h.subjects)
+                .unwrap_or_default(),
+        };
+        self.check(&header, rendered)
+    }
+
+    /// Check the text of a message, e.g. of an existing commit. Unlike
+    /// `prompt_rules`, this ignores `enabled`: that only switches off the
+    /// checks while generating, not the `lint` command.
+    pub fn lint_text(&self, text: &str) -> Vec<Violation> {
+        match self.parse_header(text) {
+            Ok(header) => self.check(&header, text),
+            Err(violation) => {
+                let mut violations = vec![violation];
+                violations.extend(self.check(&Header::default(), text));
+                violations
+            }
+        }
+    }
+
+    /// Check a commit message file. Returns `None` for messages git
+    /// generated itself, such as merges and fixups.
+    pub fn lint_file(&self, file: &Path) -> Result<Option<Report>> {
+        let message = std::fs::read_to_string(file)
+            .with_context(|| format!("Failed to read commit message from {:?}", file))?;
+        if is_generated(&message) {
+            return Ok(None);
+        }
+
+        Ok(Some(Report {
+            source: file.display().to_string(),
+            summary: message
+                .lines()
+                .find(|l| !l.trim().is_empty() && !l.starts_with('#'))
+                .unwrap_or_default()
+                .to_string(),
+            violations: self.lint_text(&message),
+        }))
+    }
+
+    /// Whether the commit type is part of the message. Plain and kernel
+    /// messages only use it for classification.
+    fn shows_type(&self) -> bool {
+        !matches!(self.style, MessageStyle::Plain | MessageStyle::Kernel)
+    }
+
+    fn check(&self, header: &Header, text: &str) -> Vec<Violation> {
+        let mut violations = Vec::new();
+
+        if let Some(commit_type) = &header.commit_type {
+            let allowed = self.allowed_types();
+            if !allowed.contains(commit_type) {
+                violations.push(Violation::new(
+                    "type-enum",
+                    format!(
+                        "type `{}` is not one of: {}",
+                        commit_type,
+                        allowed.join(", ")
+                    ),
+                ));
+            }
+        }
+
+        if let Some(scope) = &header.scope {
+            if !self.config.scopes.is_empty() && !self.config.scopes.contains(scope) {
+                violations.push(Violation::new(
+                    "scope-enum",
+                    format!(
+                        "scope `{}` is not one of: {}",
+                        scope,
+                        self.config.scopes.join(", ")
+                    ),
+                ));
+            }
+        }
+
+        let (header_lines, body_lines) = split_message(text);
+
+        let max = self.config.max_header_length;
+        for (i, line) in header_lines.iter().enumerate() {
+            let length = line.chars().count();
+            if max > 0 && length > max {
+                violations.push(Violation::new(
+                    "header-max-length",
+                    format!(
+                        "header line {} is {} characters, longer than {}",
+                        i + 1,
+                        length,
+                        max
+                    ),
+                ));
+            }
+        }
+
+        if self.config.imperative {
+            for subject in &header.subjects {
+                let word = first_word(subject);
+                if !word.is_empty() && !is_imperative(word) {
+                    violations.push(Violation::new(
+                        "subject-mood",
+                        format!(
+                            "description starts with `{}`; use the imperative mood (\"add\", not \"added\" or \"adds\")",
+                            word
+                        ),
+                    ));
+                }
+            }
+        }
+
+        let max = self.config.max_body_line_length;
+        for line in body_lines {
+            let length = line.chars().count();
+            // A single long word, e.g. a URL, cannot be wrapped
+            if max > 0 && length > max && line.trim().contains(char::is_whitespace) {
+                violations.push(Violation::new(
+                    "body-max-line-length",
+                    format!(
+                        "body line is {} characters, longer than {}: {}",
+                        length,
+                        max,
+                        excerpt(line)
+                    ),
+                ));
+            }
+        }
+
+        violations
+    }
+
+    /// Split the header into type, scope and descriptions following the
+    /// message style.
+    fn parse_header(&self, text: &str) -> std::result::Result<Header, Violation> {
+        let (lines, _) = split_message(text);
+        let Some((first, translations)) = lines.split_first() else {
+            return Err(Violation::new(
+                "header-empty",
+                "message is empty".to_string(),
+            ));
+        };
+
+        let mut header = Header::default();
+        let description = match self.style {
+            MessageStyle::Conventional | MessageStyle::Angular => {
+                // An optional type emoji leads the header
+                let line = first.trim_start_matches(|c: char| !c.is_ascii_alphanumeric());
+                let parsed = line.split_once(": ").and_then(|(prefix, description)| {
+                    let prefix = prefix.strip_suffix('!').unwrap_or(prefix);
+                    let (commit_type, scope) = match prefix.split_once('(') {
+                        Some((commit_type, scope)) => (commit_type, scope.strip_suffix(')')?),
+                        None => (prefix, ""),
+                    };
+                    let valid = !commit_type.is_empty()
+                        && commit_type
+                            .chars()
+                            .all(|c| c.is_ascii_alphanumeric() || c == '-');
+                    valid.then_some((commit_type, scope, description))
+                });
+                let Some((commit_type, scope, description)) = parsed else {
+                    return Err(Violation::new(
+                        "header-format",
+                        format!("header `{}` is not `type(scope): description`", first),
+                    ));
+                };
+                header.commit_type = Some(commit_type.to_string());
+                header.scope = Some(scope.to_string()).filter(|s| !s.is_empty());
+                description
+            }
+            MessageStyle::Gitmoji => {
+                let line = first.split_once(' ').map(|(_, rest)| rest).unwrap_or(first);
+                match line
+                    .strip_prefix('(')
+                    .and_then(|rest| rest.split_once("): "))
+                {
+                    Some((scope, description)) => {
+                        header.scope = Some(scope.to_string());
+                        description
+                    }
+                    None => line,
+                }
+            }
+            MessageStyle::Kernel => match first.split_once(": ") {
+                Some((scope, description)) if !scope.contains(' ') => {
+                    header.scope = Some(scope.to_string());
+                    description
+                }
+                _ => first,
+            },
+            MessageStyle::Plain => first,
+        };
+
+        header.subjects = std::iter::once(description)
+            .chain(translations.iter().copied())
+            .map(str::to_string)
+            .collect();
+        Ok(header)
+    }
+}
+
+/// Messages written by git itself, such as merges, reverts and fixups, which
+/// are not held to the rules.
+pub fn is_generated(message: &str) -> bool {
+    const PREFIXES: &[&str] = &["Merge ", "Revert \"", "fixup! ", "squash! ", "amend! "];
+    let message = message.trim_start();
+    PREFIXES.iter().any(|prefix| message.starts_with(prefix))
+}
+
+/// Header lines (up to the first blank line) and body lines. Comment lines,
+/// the diff below a `git commit --verbose` scissors line and trailers such as
+/// `Signed-off-by:` are not checked.
+fn split_message(text: &str) -> (Vec<&str>, Vec<&str>) {
+    let lines: Vec<&str> = text
+        .lines()
+        .map(str::trim_end)
+        .take_while(|l| !l.starts_with("# ------------------------ >8 ------------------------"))
+        .filter(|l| !l.starts_with('#'))
+        .skip_while(|l| l.is_empty())
+        .collect();
+    let end = lines
+        .iter()
+        .position(|l| l.is_empty())
+        .unwrap_or(lines.len());
+    let body = lines[end..]
+        .iter()
+        .copied()
+        .filter(|l| !l.is_empty() && !is_trailer(l))
+        .collect();
+    (lines[..end].to_vec(), body)
+}
+
+/// `Signed-off-by: ...`, `Co-authored-by: ...` and similar git trailers.
+fn is_trailer(line: &str) -> bool {
+    line.split_once(": ").is_some_and(|(key, _)| {
+        key.contains('-') && key.chars().all(|c| c.is_ascii_alphabetic() || c == '-')
+    })
+}
+
+/// The start of a long line for messages.
+fn excerpt(line: &str) -> String {
+    match line.char_indices().nth(40) {
+        Some((end, _)) => format!("{}...", &line[..end]),
+        None => line.to_string(),
+    }
+}
+
+/// First word of an English description, empty for other scripts.
+fn first_word(subject: &str) -> &str {
+    let word = subject
+        .trim()
+        .split(|c: char| !c.is_ascii_alphabetic())
+        .next()
+        .unwrap_or("");
+    if subject.trim().starts_with(word) {
+        word
+    } else {
+        ""
+    }
+}
+
+fn is_imperative(word: &str) -> bool {
+    let word = word.to_ascii_lowercase();
+    if word.len() > 3 && word.ends_with("ed") && !NOT_PAST_TENSE.contains(&word.as_str()) {
+        return false;
+    }
+    if word.len() > 4 && word.ends_with("ing") && !NOT_GERUND.contains(&word.as_str()) {
+        return false;
+    }
+    !THIRD_PERSON.contains(&word.as_str())
+}
+
+/// Ask the model to rewrite `message` so that it no longer breaks the rules.
+pub async fn fix_message<'a>(
+    chain: &'a ProviderChain,
+    message: &CommitMessage,
+    violations: &[Violation],
+    context: &CommitContext,
+    debug: bool,
+) -> Result<(CommitMessage, &'a str)> {
+    let context = CommitContext {
+        stage: PromptStage::Fix {
+            violations: violations.iter().map(|v| v.message.clone()).collect(),
+        },
+        ..context.clone()
+    };
+    let json = serde_json::to_string_pretty(message)?;
+    chain.generate_commit_message(&json, &context, debug).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rules(config: &LintConfig, style: MessageStyle, text: &str) -> Vec<&'static str> {
+        Linter::new(config, style)
+            .lint_text(text)
+            .iter()
+            .map(|v| v.rule)
+            .collect()
+    }
+
+    #[test]
+    fn test_lint_text_rules() {
+        let config = LintConfig {
+            scopes: vec!["cli".to_string()],
+            max_header_length: 30,
+            max_body_line_length: 20,
+            ..Default::default()
+        };
+        let conventional = MessageStyle::Conventional;
+
+        assert!(rules(&config, conventional, "feat(cli): add flag\n添加参数\n").is_empty());
+        assert_eq!(
+            rules(&config, conventional, "✨ feature(ui)!: Added flag"),
+            vec!["type-enum", "scope-enum", "subject-mood"]
+        );
+        assert_eq!(
+            rules(&config, conventional, "Add a flag"),
+            vec!["header-format"]
+        );
+        assert_eq!(
+            rules(
+                &config,
+                conventional,
+                "fix: updates the parser so that it works\n\nshort line\nhttps://example.com/a/very/long/link\nthis body line is far too long\n\nSigned-off-by: Someone Else <someone@example.com>"
+            ),
+            vec!["header-max-length", "subject-mood", "body-max-line-length"]
+        );
+        assert_eq!(
+            rules(&config, MessageStyle::Kernel, "net: fixing a leak"),
+            vec!["scope-enum", "subject-mood"]
+        );
+        assert!(rules(&config, MessageStyle::Plain, "Embed the icon").is_empty());
+
+        let fixable: Vec<bool> = Linter::new(&config, conventional)
+            .lint_text("feat(core): added a flag")
+            .iter()
+            .map(Violation::fixable)
+            .collect();
+        assert_eq!(fixable, vec![true, false]);
+    }
+
+    #[test]
+    fn test_lint_file_ignores_enabled() {
+        let config = LintConfig {
+            enabled: false,
+            ..Default::default()
+        };
+        let linter = Linter::new(&config, MessageStyle::Conventional);
+        assert!(linter.prompt_rules().is_empty());
+
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("COMMIT_EDITMSG");
+        std::fs::write(&file, "# comment\nfeature: Added flag\n").unwrap();
+        let report = linter.lint_file(&file).unwrap().unwrap();
+        assert_eq!(report.summary, "feature: Added flag");
+        let rules: Vec<&str> = report.violations.iter().map(|v| v.rule).collect();
+        assert_eq!(rules, vec!["type-enum", "subject-mood"]);
+
+        std::fs::write(&file, "Merge branch 'main'\n").unwrap();
+        assert!(linter.lint_file(&file).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_skips_generated_and_verbose_parts() {
+        let config = LintConfig::default();
+        let verbose = "fix: handle empty diff\n# Please enter the commit message\n# ------------------------ >8 ------------------------\ndiff --git a/a b/a\n+a line that is much longer than the body limit of one hundred characters, which is only a diff line\n";
+        assert!(rules(&config, MessageStyle::Conventional, verbose).is_empty());
+
+        assert!(is_generated("Merge branch 'main' into feature"));
+        assert!(is_generated("fixup! feat: add flag"));
+        assert!(!is_generated("feat: merge configs"));
+    }
+
+    #[test]
+    fn test_lint_message_uses_fields() {
+        let config = LintConfig::default();
+        let message = CommitMessage {
+            commit_type: "wip".to_string(),
+            description: "add flag".to_string(),
+            ..Default::default()
+        };
+        let linter = Linter::new(&config, MessageStyle::Gitmoji);
+        let violations = linter.lint_message(&message, "🔨 add flag");
+        assert_eq!(violations.len(), 1);
+        assert_eq!(violations[0].rule, "type-enum");
+
+        // Plain messages do not show the type
+        let linter = Linter::new(&config, MessageStyle::Plain);
+        assert!(linter.lint_message(&message, "add flag").is_empty());
+    }
+}
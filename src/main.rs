@@ -2,27 +2,45 @@ mod ai;
 mod cli;
 mod config;
 mod git;
+mod hooks;
+mod lint;
+mod scopes;
 mod ui;
 
 use anyhow::{Context, Result};
 use clap::Parser;
 use colored::*;
+use std::collections::HashMap;
 use std::env;
-use std::process::Command;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
 
 use crate::cli::{Args, Commands};
 use crate::config::Config;
-use crate::git::{GitRepo, LogOptions};
-use crate::ui::{CommitAction, CommitUI};
+use crate::git::{CommitOutcome, DiffFilter, GitRepo, LogOptions};
+use crate::lint::Linter;
+use crate::scopes::ScopeMap;
+use crate::ui::{CommitAction, CommitUI, Regeneration, SplitAction};
 
 #[tokio::main]
-async fn main() -> Result<()> {
+async fn main() -> Result<ExitCode> {
     let args = Args::parse();
     let path = args.path.unwrap_or_else(|| env::current_dir().unwrap());
 
     // Handle init command first (doesn't need git repo)
     if let Some(Commands::Init { local, force }) = &args.command {
-        return handle_init_command(*local, *force);
+        handle_init_command(*local, *force)?;
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    // A message file can be checked outside of a repository, e.g. in CI
+    if let Some(Commands::Lint {
+        file: Some(file),
+        json,
+        ..
+    }) = &args.command
+    {
+        return handle_lint_file_command(file, *json);
     }
 
     // Check if it's a git repository
@@ -37,7 +55,7 @@ async fn main() -> Result<()> {
                 "{}",
                 "Tip: Run 'git init' to initialize a Git repository".yellow()
             );
-            return Ok(());
+            return Ok(ExitCode::SUCCESS);
         }
     };
 
@@ -50,8 +68,40 @@ async fn main() -> Result<()> {
             auto,
             show_diff,
             debug,
+            map_reduce,
+            amend,
+            candidates,
+        }) => {
+            handle_commit_command(
+                repo,
+                api_key,
+                model,
+                base_url,
+                auto,
+                show_diff,
+                debug,
+                map_reduce,
+                amend,
+                candidates.into(),
+            )
+            .await?;
+        }
+        Some(Commands::Split {
+            api_key,
+            model,
+            base_url,
+            debug,
         }) => {
-            handle_commit_command(repo, api_key, model, base_url, auto, show_diff, debug).await?;
+            handle_split_command(repo, api_key, model, base_url, debug).await?;
+        }
+        Some(Commands::InstallHook { force }) => {
+            handle_install_hook_command(&repo, force)?;
+        }
+        Some(Commands::PrepareCommitMsg { file, source, .. }) => {
+            handle_prepare_commit_msg_command(repo, file, source).await?;
+        }
+        Some(Commands::Lint { range, json, .. }) => {
+            return handle_lint_command(&repo, range, json);
         }
         Some(Commands::Diff { staged }) => {
             handle_diff_command(repo, staged)?;
@@ -82,7 +132,7 @@ async fn main() -> Result<()> {
         }
     }
 
-    Ok(())
+    Ok(ExitCode::SUCCESS)
 }
 
 fn handle_init_command(local: bool, force: bool) -> Result<()> {
@@ -215,13 +265,73 @@ fn handle_status_command(repo: GitRepo, verbose: bool) -> Result<()> {
     Ok(())
 }
 
+/// Check the messages of existing commits against `[lint]`.
+fn handle_lint_command(repo: &GitRepo, range: Option<String>, json: bool) -> Result<ExitCode> {
+    // A broken config must not silently gate on the default rules
+    let config = Config::load()?;
+    let linter = Linter::new(&config.lint, config.commit.format.parse()?);
+
+    // A single revision is checked on its own, a range commit by commit
+    let rev = range.unwrap_or_else(|| "HEAD".to_string());
+    let options = LogOptions {
+        count: if rev.contains("..") { usize::MAX } else { 1 },
+        range: Some(rev),
+        grep: None,
+        author: None,
+        since: None,
+        until: None,
+    };
+    let commits = repo.get_commits(&options)?;
+
+    let reports = commits
+        .iter()
+        .filter(|commit| !lint::is_generated(&commit.message))
+        .map(|commit| lint::Report {
+            source: commit.short_id.clone(),
+            summary: commit.summary.clone(),
+            violations: linter.lint_text(&commit.message),
+        })
+        .collect();
+    show_lint_reports(reports, json)
+}
+
+/// Check a commit message file against `[lint]`.
+fn handle_lint_file_command(file: &Path, json: bool) -> Result<ExitCode> {
+    let config = Config::load()?;
+    let linter = Linter::new(&config.lint, config.commit.format.parse()?);
+
+    let reports = linter.lint_file(file)?.into_iter().collect();
+    show_lint_reports(reports, json)
+}
+
+/// Print lint results and exit with status 1 if any message breaks a rule.
+fn show_lint_reports(reports: Vec<lint::Report>, json: bool) -> Result<ExitCode> {
+    let checked = reports.len();
+    let failed: Vec<lint::Report> = reports
+        .into_iter()
+        .filter(|report| !report.violations.is_empty())
+        .collect();
+
+    if json {
+        let output = serde_json::json!({ "checked": checked, "failed": failed });
+        println!("{}", serde_json::to_string_pretty(&output)?);
+    } else {
+        CommitUI::show_lint_reports(&failed, checked);
+    }
+
+    Ok(match failed.is_empty() {
+        true => ExitCode::SUCCESS,
+        false => ExitCode::FAILURE,
+    })
+}
+
 fn handle_diff_command(repo: GitRepo, staged: bool) -> Result<()> {
     let diff = if staged {
         println!("{}", "Showing staged changes:".bold().green());
-        repo.get_diff(true)?
+        repo.get_diff(true, None)?
     } else {
         println!("{}", "Showing all changes:".bold().green());
-        repo.get_combined_diff()?
+        repo.get_combined_diff(None)?
     };
 
     if diff.is_empty() {
@@ -249,6 +359,7 @@ async fn handle_log_command(
 ) -> Result<()> {
     let options = LogOptions {
         count,
+        range: None,
         grep,
         author,
         since: since.clone(),
@@ -353,9 +464,7 @@ async fn handle_log_command(
         // Generate AI changelog
         let config = Config::load().unwrap_or_default();
 
-        let api_key = api_key
-            .or_else(|| config.get_api_key())
-            .or_else(|| CommitUI::get_api_key(&config.ai.provider).ok());
+        let api_key = resolve_api_key(api_key, &config);
 
         let api_key = match api_key {
             Some(key) => key,
@@ -368,16 +477,7 @@ async fn handle_log_command(
             }
         };
 
-        let final_model = model.unwrap_or(config.ai.model.clone());
-        let final_base_url = base_url.or(config.ai.base_url.clone());
-
-        let client = ai::create_client(
-            &config.ai.provider,
-            api_key,
-            final_model,
-            final_base_url,
-            config.ai.max_tokens,
-        )?;
+        let providers = build_provider_chain(&config, api_key, model, base_url, debug)?;
 
         // Build date range string
         let date_range = if !selected_commits.is_empty() {
@@ -403,10 +503,14 @@ async fn handle_log_command(
 
         CommitUI::show_info("Generating AI changelog summary...");
 
-        let changelog = client
+        let (changelog, provider_label) = providers
             .generate_changelog(&selected_commits, &context, debug)
             .await?;
 
+        if providers.len() > 1 {
+            CommitUI::show_info(&format!("Generated by {}", provider_label));
+        }
+
         let changelog_text = changelog.format_display();
 
         println!("\n{}", "=".repeat(60));
@@ -464,6 +568,7 @@ async fn handle_log_command(
     Ok(())
 }
 
+#[allow(clippy::too_many_arguments)]
 async fn handle_commit_command(
     repo: GitRepo,
     api_key: Option<String>,
@@ -472,33 +577,64 @@ async fn handle_commit_command(
     auto: bool,
     show_diff: bool,
     debug: bool,
+    map_reduce: bool,
+    amend: bool,
+    candidate_count: usize,
 ) -> Result<()> {
     // Load config
     let config = Config::load().unwrap_or_default();
 
+    if amend {
+        check_amend_allowed(&repo)?;
+    }
+
     // Check for changes
     let status = repo.get_status()?;
-    if status.is_clean {
+    if status.is_clean && !amend {
         CommitUI::show_info("No changes to commit");
         return Ok(());
     }
 
     // Check for unstaged changes and prompt to stage
-    check_and_stage_changes()?;
+    check_and_stage_changes(&repo, config.commit.auto_stage)?;
 
-    // Get diff - this should now include staged changes
-    let diff = repo.get_combined_diff()?;
+    // Run pre-commit before spending an AI request; it may also restage files
+    repo.run_pre_commit_hook()?;
+    let status = repo.get_status()?;
+
+    // Get diff. In staged-only mode it is exactly the index that `git commit` records.
+    let filter = DiffFilter::new(&config.commit.exclude)?;
+    let diff = if amend {
+        // HEAD's own changes plus anything staged since
+        repo.get_amend_diff(Some(&filter))?
+    } else if config.commit.staged_only {
+        if !status.unstaged_files.is_empty() {
+            CommitUI::show_info(&format!(
+                "{} file(s) with unstaged changes will not be included in this commit",
+                status.unstaged_files.len()
+            ));
+        }
+        repo.get_diff(true, Some(&filter))?
+    } else {
+        repo.get_combined_diff(Some(&filter))?
+    };
 
     // Debug: Check if we're getting the staged diff correctly
     if debug {
         println!("Debug: Combined diff length: {}", diff.len());
-        if diff.len() > 100 {
-            println!("Debug: First 100 chars of diff: {}", &diff[..100]);
+        if let Some((end, _)) = diff.char_indices().nth(100) {
+            println!("Debug: First 100 chars of diff: {}", &diff[..end]);
         }
     }
 
     if diff.is_empty() {
-        CommitUI::show_info("No changes detected");
+        if amend {
+            CommitUI::show_info("HEAD has no changes to describe");
+        } else if config.commit.staged_only {
+            CommitUI::show_info("No staged changes to commit. Stage files with `git add` first.");
+        } else {
+            CommitUI::show_info("No changes detected");
+        }
         return Ok(());
     }
 
@@ -508,156 +644,815 @@ async fn handle_commit_command(
         return Ok(());
     }
 
-    // Get API key
-    let api_key = api_key
-        .or_else(|| config.get_api_key())
-        .or_else(|| CommitUI::get_api_key(&config.ai.provider).ok())
-        .context("No API key provided")?;
+    // Create AI clients (primary provider plus configured fallbacks)
+    let mut model = model;
+    let mut selected = 0;
+    let mut providers = provider_chain_for(&config, selected, &api_key, &model, &base_url, debug)?;
 
-    // Count changes for context
-    let added_lines = diff.lines().filter(|l| l.starts_with('+')).count();
-    let removed_lines = diff.lines().filter(|l| l.starts_with('-')).count();
+    let file_count = if amend {
+        git::parse_diff(&diff).len()
+    } else if config.commit.staged_only {
+        status.staged_files.len()
+    } else {
+        status.total_changes()
+    };
+    let mut hints: Vec<String> = Vec::new();
+    let generated = if candidate_count > 1 && !uses_map_reduce(&config, &diff, map_reduce) {
+        generate_candidates(
+            &repo,
+            &config,
+            &providers,
+            &diff,
+            file_count,
+            candidate_count,
+            debug,
+        )
+        .await?
+    } else {
+        if candidate_count > 1 {
+            CommitUI::show_info(
+                "Large diff: generating a single message in chunks instead of candidates",
+            );
+        }
+        vec![
+            generate_message(
+                &repo, &config, &providers, &diff, file_count, &hints, map_reduce, debug,
+            )
+            .await?,
+        ]
+    };
 
-    // Get branch info
-    let branch_info = repo.get_branch_info()?;
+    // Violations left after generation, checked on the message fields
+    let mut rule_violations = HashMap::new();
+    let mut candidates = Vec::new();
+    for (label, message, violations) in generated {
+        rule_violations.insert(message.clone(), violations);
+        candidates.push((label, message));
+    }
 
-    // Create context
-    let context = ai::CommitContext {
-        branch_name: branch_info.name,
-        file_count: status.total_changes(),
-        added_lines,
-        removed_lines,
+    let mut message = if candidates.len() > 1 && !auto {
+        let index = CommitUI::select_candidate(&candidates, 0)?;
+        candidates[index].1.clone()
+    } else {
+        candidates[0].1.clone()
     };
 
-    // Create AI client
-    // Use model from CLI if provided, otherwise use config
-    let final_model = model.unwrap_or(config.ai.model.clone());
-    // Use base_url from CLI if provided, otherwise use config
-    let final_base_url = base_url.or(config.ai.base_url.clone());
-    let client = ai::create_client(
-        &config.ai.provider,
-        api_key,
-        final_model,
-        final_base_url,
-        config.ai.max_tokens,
-    )?;
+    if amend {
+        CommitUI::show_comparison(
+            "Amending HEAD:",
+            ("Current message", &repo.head_message()?),
+            ("New message", &message),
+        );
+    }
 
-    CommitUI::show_info("Generating commit message with AI...");
+    // Handle user action. A message rejected by a hook is shown again so it can be edited.
+    let linter = Linter::new(&config.lint, config.commit.format.parse()?);
+    loop {
+        let violations = match rule_violations.get(&message) {
+            Some(violations) => violations.clone(),
+            None if config.lint.enabled => linter.lint_text(&message),
+            None => Vec::new(),
+        };
+        let action = if auto {
+            CommitUI::show_violations(&violations);
+            CommitAction::Accept
+        } else {
+            CommitUI::confirm_commit(&message, &violations, candidates.len())?
+        };
 
-    // Generate commit message
-    let commit_message = client
-        .generate_commit_message(&diff, &context, debug)
-        .await?;
+        let (candidate, edited) = match action {
+            CommitAction::Accept => (message.clone(), false),
+            CommitAction::Edit(edited_message) => (edited_message, true),
+            CommitAction::Regenerate => {
+                let labels: Vec<String> = config
+                    .providers()
+                    .iter()
+                    .map(|p| format!("{} ({})", p.provider, p.model))
+                    .collect();
+                let current_model = model
+                    .clone()
+                    .unwrap_or_else(|| config.providers()[selected].model.clone());
+
+                match CommitUI::ask_regeneration(&current_model, &labels, selected)? {
+                    Regeneration::Again => {}
+                    Regeneration::Hint(hint) => hints.push(hint),
+                    Regeneration::Model(name) => {
+                        model = Some(name);
+                        providers = provider_chain_for(
+                            &config, selected, &api_key, &model, &base_url, debug,
+                        )?;
+                    }
+                    Regeneration::Provider(index) => {
+                        // The new provider uses its configured model
+                        selected = index;
+                        model = None;
+                        providers = provider_chain_for(
+                            &config, selected, &api_key, &model, &base_url, debug,
+                        )?;
+                    }
+                }
 
-    // Handle user action
-    let action = if auto {
-        CommitAction::Accept
-    } else {
-        CommitUI::confirm_commit(&commit_message)?
+                // Keep the session going if this attempt fails
+                match generate_message(
+                    &repo, &config, &providers, &diff, file_count, &hints, map_reduce, debug,
+                )
+                .await
+                {
+                    Ok((mut label, regenerated, violations)) => {
+                        CommitUI::show_comparison(
+                            "Regenerated Commit Message:",
+                            ("Previous", &message),
+                            ("New", &regenerated),
+                        );
+                        if !hints.is_empty() {
+                            label = format!("{}, hints: {}", label, hints.join("; "));
+                        }
+                        rule_violations.insert(regenerated.clone(), violations);
+                        candidates.push((label, regenerated.clone()));
+                        message = regenerated;
+                    }
+                    Err(e) => eprintln!("{} {:#}", "Error:".red().bold(), e),
+                }
+                continue;
+            }
+            CommitAction::PickCandidate => {
+                let index = CommitUI::select_candidate(&candidates, candidates.len() - 1)?;
+                message = candidates[index].1.clone();
+                continue;
+            }
+            CommitAction::Cancel => {
+                CommitUI::show_info("Commit cancelled");
+                return Ok(());
+            }
+        };
+
+        let outcome = if amend {
+            repo.amend(&candidate)
+        } else {
+            repo.commit(&candidate)
+        };
+        match outcome.context("Git commit failed")? {
+            CommitOutcome::Committed {
+                id,
+                message: committed,
+            } => {
+                if committed.trim_end() != candidate.trim_end() {
+                    CommitUI::show_hook_rewrite(&committed);
+                }
+                let short_id = &id.to_string()[..7];
+                if amend {
+                    CommitUI::show_success(&format!("Commit amended! ({})", short_id));
+                } else if edited {
+                    CommitUI::show_success(&format!(
+                        "Changes committed with edited message! ({})",
+                        short_id
+                    ));
+                } else {
+                    CommitUI::show_success(&format!(
+                        "Changes committed successfully! ({})",
+                        short_id
+                    ));
+                }
+                return Ok(());
+            }
+            CommitOutcome::Rejected { hook, output } => {
+                CommitUI::show_hook_rejection(&hook, &output);
+                if auto {
+                    anyhow::bail!("{} hook rejected the commit message", hook);
+                }
+                message = candidate;
+            }
+        }
+    }
+}
+
+async fn handle_split_command(
+    repo: GitRepo,
+    api_key: Option<String>,
+    model: Option<String>,
+    base_url: Option<String>,
+    debug: bool,
+) -> Result<()> {
+    use dialoguer::{theme::ColorfulTheme, Confirm};
+
+    let config = Config::load().unwrap_or_default();
+
+    let status = repo.get_status()?;
+    if status.is_clean {
+        CommitUI::show_info("No changes to commit");
+        return Ok(());
+    }
+
+    // Every commit is built from a subset of the working tree, starting from HEAD
+    if !status.staged_files.is_empty() {
+        let unstage = Confirm::with_theme(&ColorfulTheme::default())
+            .with_prompt("Some changes are already staged. Unstage them so they can be split too (git reset)?")
+            .default(true)
+            .interact()?;
+        if !unstage {
+            CommitUI::show_info("Split cancelled");
+            return Ok(());
+        }
+        repo.unstage_all()?;
+    }
+
+    let filter = DiffFilter::new(&config.commit.exclude)?;
+    let files = repo.get_unstaged_files(Some(&filter))?;
+    if files.is_empty() {
+        CommitUI::show_info("No changes detected");
+        return Ok(());
+    }
+
+    let api_key = resolve_api_key(api_key, &config).context("No API key provided")?;
+    let providers = build_provider_chain(&config, api_key, model, base_url, debug)?;
+
+    let style = message_style(&config)?;
+    let scopes = ScopeMap::load(&repo, &config.commit)?;
+    let context = ai::CommitContext {
+        branch_name: repo.get_branch_info()?.name,
+        file_count: files.len(),
+        added_lines: files.iter().map(|f| f.added).sum(),
+        removed_lines: files.iter().map(|f| f.removed).sum(),
+        languages: config.commit.languages.clone(),
+        style,
+        max_diff_size: config.commit.max_diff_size,
+        stage: ai::PromptStage::Split,
+        hints: Vec::new(),
+        rules: Linter::new(&config.lint, style).prompt_rules(),
+        // Each commit touches only some of the files, so offer every scope
+        common_scope: None,
+        changed_scopes: Vec::new(),
+        known_scopes: scopes.known(),
+        enforce_scopes: scopes.enforced,
     };
 
-    match action {
-        CommitAction::Accept => {
-            execute_commit(&commit_message.format_conventional())?;
-            CommitUI::show_success("Changes committed successfully!");
+    CommitUI::show_info("Grouping changes into commits with AI...");
+    let (mut groups, provider_label) = ai::split::plan_commits(
+        &providers,
+        &files,
+        &context,
+        config.commit.include_emoji,
+        debug,
+    )
+    .await?;
+    if providers.len() > 1 {
+        CommitUI::show_info(&format!("Generated by {}", provider_label));
+    }
+
+    loop {
+        CommitUI::show_commit_groups(&groups, &files);
+        match CommitUI::select_split_action(&groups)? {
+            SplitAction::CommitAll if groups.iter().any(|g| g.is_unassigned()) => {
+                println!(
+                    "{}",
+                    "Some changes are not assigned to a commit yet; give them a message or merge them into another commit".yellow()
+                );
+            }
+            SplitAction::CommitAll => break,
+            SplitAction::Edit(index, message) => groups[index].message = message,
+            SplitAction::Move { from, to } => {
+                let group = groups.remove(from);
+                groups.insert(to, group);
+            }
+            SplitAction::Merge(indices) => ai::split::merge_groups(&mut groups, &indices),
+            SplitAction::Review => {}
+            SplitAction::Cancel => {
+                CommitUI::show_info("Split cancelled");
+                return Ok(());
+            }
         }
-        CommitAction::Edit(edited_message) => {
-            execute_commit(&edited_message)?;
-            CommitUI::show_success("Changes committed with edited message!");
+    }
+
+    // Stage and commit each group in turn
+    let total = groups.len();
+    let mut state = ai::split::StagingState::new(&files);
+    for (i, group) in groups.iter().enumerate() {
+        let stopped = || {
+            format!(
+                "Stopped at commit {} of {}; its changes are staged and the rest are left in the working tree",
+                i + 1,
+                total
+            )
+        };
+
+        for step in state.take(group) {
+            match step {
+                ai::split::StageStep::WholeFile(file) => repo.stage_file(&files[file].path),
+                ai::split::StageStep::Hunks(file, hunks) => {
+                    repo.stage_hunks(&files[file].path, &hunks)
+                }
+            }
+            .with_context(stopped)?;
         }
-        CommitAction::Regenerate => {
-            CommitUI::show_info("Please run the command again to regenerate");
+        repo.run_pre_commit_hook().with_context(stopped)?;
+
+        match repo.commit(&group.message).with_context(stopped)? {
+            CommitOutcome::Committed { id, message } => {
+                let subject = message.lines().next().unwrap_or_default();
+                CommitUI::show_success(&format!(
+                    "[{}/{}] {} {}",
+                    i + 1,
+                    total,
+                    &id.to_string()[..7],
+                    subject
+                ));
+            }
+            CommitOutcome::Rejected { hook, output } => {
+                CommitUI::show_hook_rejection(&hook, &output);
+                anyhow::bail!("{}", stopped());
+            }
         }
-        CommitAction::Cancel => {
-            CommitUI::show_info("Commit cancelled");
+    }
+
+    Ok(())
+}
+
+/// Amending needs a commit, and must not rewrite one that is already pushed.
+fn check_amend_allowed(repo: &GitRepo) -> Result<()> {
+    let branch_info = repo.get_branch_info()?;
+    if branch_info.name.as_deref() == Some("unborn") {
+        anyhow::bail!("There is no commit to amend yet");
+    }
+
+    // HEAD is on the upstream unless it is one of the commits ahead of it
+    if let Some(tracking) = branch_info.tracking_info {
+        if tracking.ahead == 0 {
+            anyhow::bail!(
+                "HEAD is already pushed to {}. Amending it would rewrite published history.",
+                tracking.upstream
+            );
         }
     }
+    Ok(())
+}
+
+fn handle_install_hook_command(repo: &GitRepo, force: bool) -> Result<()> {
+    let exe = env::current_exe().context("Failed to locate the rust-git-cli executable")?;
+    let path = repo.install_prepare_commit_msg_hook(&exe, force)?;
 
+    CommitUI::show_success(&format!(
+        "Installed prepare-commit-msg hook at {}",
+        path.display()
+    ));
+    CommitUI::show_info(
+        "`git commit` now starts with an AI generated message (skipped for -m, merges and amends)",
+    );
     Ok(())
 }
 
-fn check_and_stage_changes() -> Result<()> {
-    use crate::ui::CommitUI;
-    use dialoguer::{theme::ColorfulTheme, Confirm};
+/// Hook mode: write a generated message into git's commit message file.
+async fn handle_prepare_commit_msg_command(
+    repo: GitRepo,
+    file: PathBuf,
+    source: Option<String>,
+) -> Result<()> {
+    if hooks::keeps_existing_message(source.as_deref()) {
+        return Ok(());
+    }
 
-    // Check if there are unstaged changes
-    let status_output = Command::new("git")
-        .args(["status", "--porcelain"])
-        .output()
-        .context("Failed to check git status")?;
-
-    if status_output.status.success() {
-        let status = String::from_utf8_lossy(&status_output.stdout);
-        let has_unstaged = status.lines().any(|line| {
-            line.starts_with(" M")
-                || line.starts_with("??")
-                || line.starts_with(" D")
-                || line.starts_with(" A")
-        });
-
-        if has_unstaged {
-            println!("\n{}", "Unstaged changes detected:".yellow());
-            println!("{}", "─".repeat(50));
-
-            // Show unstaged files
-            for line in status.lines() {
-                if line.starts_with(" M") {
-                    println!("  {} {}", "M".yellow(), &line[3..]);
-                } else if line.starts_with("??") {
-                    println!("  {} {}", "?".red(), &line[3..]);
-                } else if line.starts_with(" D") {
-                    println!("  {} {}", "D".red(), &line[3..]);
+    // Never block the commit: report the problem and leave the file untouched
+    if let Err(e) = fill_commit_message_file(&repo, &file).await {
+        eprintln!(
+            "{} Could not generate a commit message: {:#}",
+            "⚠".yellow(),
+            e
+        );
+    }
+    Ok(())
+}
+
+async fn fill_commit_message_file(repo: &GitRepo, file: &Path) -> Result<()> {
+    let config = Config::load().unwrap_or_default();
+
+    // `git commit -a` and `git commit <paths>` stage into a temporary index
+    if let Some(index_file) = env::var_os("GIT_INDEX_FILE") {
+        repo.use_index_file(Path::new(&index_file))?;
+    }
+
+    let filter = DiffFilter::new(&config.commit.exclude)?;
+    let diff = repo.get_diff(true, Some(&filter))?;
+    if diff.is_empty() {
+        return Ok(());
+    }
+
+    // There is no terminal to prompt on, so the key must be configured
+    let api_key = match config.get_api_key() {
+        Some(key) => key,
+        None if !ai::requires_api_key(&config.ai.provider) => String::new(),
+        None => anyhow::bail!("No API key configured for {}", config.ai.provider),
+    };
+    let providers = build_provider_chain(&config, api_key, None, None, false)?;
+
+    let file_count = repo.get_status()?.staged_files.len();
+    let (_, message, _) = generate_message(
+        repo,
+        &config,
+        &providers,
+        &diff,
+        file_count,
+        &[],
+        false,
+        false,
+    )
+    .await?;
+
+    // Keep git's template and comment lines below the generated message
+    let existing = std::fs::read_to_string(file).unwrap_or_default();
+    std::fs::write(file, format!("{}\n{}", message.trim_end(), existing))
+        .with_context(|| format!("Failed to write commit message to {:?}", file))?;
+    Ok(())
+}
+
+/// Generate a commit message for `diff` and render it as configured by
+/// `commit.format`. Returns the label of the provider that wrote it, the
+/// message and the `[lint]` violations it still has.
+#[allow(clippy::too_many_arguments)]
+async fn generate_message(
+    repo: &GitRepo,
+    config: &Config,
+    providers: &ai::ProviderChain,
+    diff: &str,
+    file_count: usize,
+    hints: &[String],
+    map_reduce: bool,
+    debug: bool,
+) -> Result<(String, String, Vec<lint::Violation>)> {
+    let context = commit_context(repo, config, diff, file_count, hints)?;
+
+    CommitUI::show_info("Generating commit message with AI...");
+
+    // Generate commit message, in chunks when the diff is too large for one prompt
+    let (mut commit_message, provider_label) = if uses_map_reduce(config, diff, map_reduce) {
+        let options = ai::mapreduce::MapReduceOptions {
+            chunk_size: match config.commit.max_diff_size {
+                0 => ai::mapreduce::DEFAULT_CHUNK_SIZE,
+                size => size,
+            },
+            concurrency: config.commit.max_concurrency,
+        };
+        let on_progress = |progress: ai::mapreduce::Progress| match progress {
+            ai::mapreduce::Progress::Started { total } => CommitUI::show_info(&format!(
+                "Diff is large, summarizing it in {} parts...",
+                total
+            )),
+            ai::mapreduce::Progress::Summarized {
+                index,
+                total,
+                label,
+            } => CommitUI::show_info(&format!("Summarized part {}/{}: {}", index, total, label)),
+        };
+        ai::mapreduce::generate_commit_message(
+            providers,
+            diff,
+            &context,
+            options,
+            on_progress,
+            debug,
+        )
+        .await?
+    } else {
+        providers
+            .generate_commit_message(diff, &context, debug)
+            .await?
+    };
+    context.constrain_scope(&mut commit_message);
+    let (commit_message, violations) =
+        enforce_rules(providers, config, &context, commit_message, debug).await;
+
+    if providers.len() > 1 {
+        CommitUI::show_info(&format!("Generated by {}", provider_label));
+    }
+
+    Ok((
+        provider_label.to_string(),
+        commit_message.render(
+            context.style,
+            &config.commit.languages,
+            config.commit.include_emoji,
+        ),
+        violations,
+    ))
+}
+
+/// Generate `count` alternative messages in parallel, each like the result
+/// of `generate_message`.
+async fn generate_candidates(
+    repo: &GitRepo,
+    config: &Config,
+    providers: &ai::ProviderChain,
+    diff: &str,
+    file_count: usize,
+    count: usize,
+    debug: bool,
+) -> Result<Vec<(String, String, Vec<lint::Violation>)>> {
+    let context = commit_context(repo, config, diff, file_count, &[])?;
+
+    CommitUI::show_info(&format!(
+        "Generating {} candidate messages with AI...",
+        count
+    ));
+    let messages = providers
+        .generate_commit_messages(diff, &context, count, debug)
+        .await?;
+    let messages = futures::future::join_all(messages.into_iter().map(|(mut message, label)| {
+        let context = &context;
+        async move {
+            context.constrain_scope(&mut message);
+            let (message, violations) =
+                enforce_rules(providers, config, context, message, debug).await;
+            (message, label, violations)
+        }
+    }))
+    .await;
+    if messages.len() < count {
+        CommitUI::show_info(&format!(
+            "Only {} of {} candidates could be generated",
+            messages.len(),
+            count
+        ));
+    }
+
+    Ok(messages
+        .into_iter()
+        .map(|(message, label, violations)| {
+            (
+                label.to_string(),
+                message.render(
+                    context.style,
+                    &config.commit.languages,
+                    config.commit.include_emoji,
+                ),
+                violations,
+            )
+        })
+        .collect())
+}
+
+/// Check a generated message against `[lint]` and, if it breaks any rule,
+/// ask the model once to fix it. The message is kept as is if that fails.
+/// Returns the message with the violations it still has.
+async fn enforce_rules(
+    providers: &ai::ProviderChain,
+    config: &Config,
+    context: &ai::CommitContext,
+    message: ai::CommitMessage,
+    debug: bool,
+) -> (ai::CommitMessage, Vec<lint::Violation>) {
+    if !config.lint.enabled {
+        return (message, Vec::new());
+    }
+
+    let linter = Linter::new(&config.lint, context.style);
+    let lint = |message: &ai::CommitMessage| {
+        let rendered = message.render(
+            context.style,
+            &config.commit.languages,
+            config.commit.include_emoji,
+        );
+        linter.lint_message(message, &rendered)
+    };
+
+    let violations = lint(&message);
+    let fixable: Vec<lint::Violation> =
+        violations.iter().filter(|v| v.fixable()).cloned().collect();
+    if fixable.is_empty() || !config.lint.fix {
+        return (message, violations);
+    }
+
+    CommitUI::show_info(&format!(
+        "Message breaks {} commit rule(s), asking the AI to fix it...",
+        fixable.len()
+    ));
+    match lint::fix_message(providers, &message, &fixable, context, debug).await {
+        Ok((mut fixed, _)) => {
+            // The fix is a new answer, held to the same scope and rules
+            context.constrain_scope(&mut fixed);
+            let violations = lint(&fixed);
+            (fixed, violations)
+        }
+        Err(e) => {
+            eprintln!("{} Could not fix the message: {:#}", "⚠".yellow(), e);
+            (message, violations)
+        }
+    }
+}
+
+/// The `commit.format` style. `include_emoji` only changes the conventional
+/// header (gitmoji always has one), so warn when it would be ignored.
+fn message_style(config: &Config) -> Result<ai::MessageStyle> {
+    let style = config.commit.format.parse()?;
+    if config.commit.include_emoji
+        && !matches!(
+            style,
+            ai::MessageStyle::Conventional | ai::MessageStyle::Gitmoji
+        )
+    {
+        eprintln!(
+            "{} commit.include_emoji is ignored by the {} format",
+            "⚠".yellow(),
+            config.commit.format
+        );
+    }
+    Ok(style)
+}
+
+/// Prompt context for generating a message from `diff`.
+fn commit_context(
+    repo: &GitRepo,
+    config: &Config,
+    diff: &str,
+    file_count: usize,
+    hints: &[String],
+) -> Result<ai::CommitContext> {
+    // Count changes for context
+    let added_lines = diff.lines().filter(|l| l.starts_with('+')).count();
+    let removed_lines = diff.lines().filter(|l| l.starts_with('-')).count();
+
+    let style = message_style(config)?;
+    let scopes = ScopeMap::load(repo, &config.commit)?;
+    let changed = git::parse_diff(diff);
+
+    Ok(ai::CommitContext {
+        branch_name: repo.get_branch_info()?.name,
+        file_count,
+        added_lines,
+        removed_lines,
+        languages: config.commit.languages.clone(),
+        style,
+        max_diff_size: config.commit.max_diff_size,
+        stage: ai::PromptStage::Full,
+        hints: hints.to_vec(),
+        rules: Linter::new(&config.lint, style).prompt_rules(),
+        common_scope: scopes.common(changed.iter().map(|f| f.path.as_str())),
+        changed_scopes: scopes.changed(changed.iter().map(|f| f.path.as_str())),
+        known_scopes: scopes.known(),
+        enforce_scopes: scopes.enforced,
+    })
+}
+
+/// Whether the message is generated in chunks: forced by `--map-reduce` or
+/// because the diff exceeds `commit.map_reduce_threshold`.
+fn uses_map_reduce(config: &Config, diff: &str, map_reduce: bool) -> bool {
+    let threshold = config.commit.map_reduce_threshold;
+    map_reduce || (threshold > 0 && diff.len() > threshold)
+}
+
+/// Provider chain with configured provider `index` (see `Config::providers`)
+/// as the primary. CLI overrides for the API key and base URL only apply to
+/// the `[ai]` provider.
+fn provider_chain_for(
+    config: &Config,
+    index: usize,
+    api_key: &Option<String>,
+    model: &Option<String>,
+    base_url: &Option<String>,
+    debug: bool,
+) -> Result<ai::ProviderChain> {
+    let config = config.with_primary(index);
+    let (api_key, base_url) = match index {
+        0 => (api_key.clone(), base_url.clone()),
+        _ => (None, None),
+    };
+
+    let api_key = resolve_api_key(api_key, &config).context("No API key provided")?;
+    build_provider_chain(&config, api_key, model.clone(), base_url, debug)
+}
+
+/// Build the primary AI client followed by the `[[fallback]]` providers from config.
+/// CLI overrides for model and base URL only apply to the primary provider.
+fn build_provider_chain(
+    config: &Config,
+    api_key: String,
+    model: Option<String>,
+    base_url: Option<String>,
+    debug: bool,
+) -> Result<ai::ProviderChain> {
+    let registry = ai::ProviderRegistry::with_gateways(&config.gateways);
+    let mut chain = ai::ProviderChain::new();
+
+    // Use model and base_url from CLI if provided, otherwise use config
+    let primary = ai::ProviderOptions {
+        model: model.unwrap_or(config.ai.model.clone()),
+        base_url: base_url.or(config.ai.base_url.clone()),
+        ..ai::ProviderOptions::from_config(&config.ai, api_key)
+    };
+    chain.push(
+        format!("{} ({})", config.ai.provider, primary.model),
+        registry.create(&config.ai.provider, primary)?,
+    );
+
+    for fallback in &config.fallback {
+        let api_key = match fallback.get_api_key() {
+            Some(key) => key,
+            None if !ai::requires_api_key(&fallback.provider) => String::new(),
+            None => {
+                if debug {
+                    eprintln!(
+                        "Debug: Skipping fallback provider {} ({}): no API key in {}",
+                        fallback.provider, fallback.model, fallback.api_key_env
+                    );
                 }
+                continue;
             }
-            println!("{}", "─".repeat(50));
-
-            let should_stage = Confirm::with_theme(&ColorfulTheme::default())
-                .with_prompt("Do you want to stage all changes (git add .)?")
-                .default(true)
-                .interact()?;
-
-            if should_stage {
-                let add_output = Command::new("git")
-                    .args(["add", "."])
-                    .output()
-                    .context("Failed to execute git add command")?;
-
-                if !add_output.status.success() {
-                    let error = String::from_utf8_lossy(&add_output.stderr);
-                    anyhow::bail!("Failed to stage changes: {}", error.trim());
-                }
+        };
 
-                CommitUI::show_info("All changes staged successfully");
-            } else {
-                CommitUI::show_info("Proceeding with only currently staged changes");
-            }
+        let label = format!("{} ({})", fallback.provider, fallback.model);
+        match registry.create(
+            &fallback.provider,
+            ai::ProviderOptions::from_config(fallback, api_key),
+        ) {
+            Ok(client) => chain.push(label, client),
+            // A broken fallback must not stop the primary provider from being used
+            Err(e) => eprintln!(
+                "{}",
+                format!("Skipping fallback provider {}: {:#}", label, e).yellow()
+            ),
         }
     }
 
-    Ok(())
+    Ok(chain)
 }
 
-fn execute_commit(message: &str) -> Result<()> {
-    // Execute git commit
-    let commit_output = Command::new("git")
-        .args(["commit", "-m", message])
-        .output()
-        .context("Failed to execute git commit command")?;
-
-    if !commit_output.status.success() {
-        let error = String::from_utf8_lossy(&commit_output.stderr);
-
-        // Check for common git errors and provide helpful messages
-        let error_msg = if error.contains("nothing to commit") {
-            "No changes to commit. All changes may already be committed."
-        } else if error.contains("Please tell me who you are") {
-            "Git user not configured. Please run:\n  git config --global user.email \"you@example.com\"\n  git config --global user.name \"Your Name\""
+/// Resolve the API key from CLI, config or an interactive prompt.
+/// Providers that run locally (e.g. Ollama) don't need one.
+fn resolve_api_key(api_key: Option<String>, config: &Config) -> Option<String> {
+    let api_key = api_key.or_else(|| config.get_api_key());
+
+    if !ai::requires_api_key(&config.ai.provider) {
+        return Some(api_key.unwrap_or_default());
+    }
+
+    api_key.or_else(|| CommitUI::get_api_key(&config.ai.provider).ok())
+}
+
+fn check_and_stage_changes(repo: &GitRepo, auto_stage: bool) -> Result<()> {
+    use crate::ui::CommitUI;
+    use dialoguer::{theme::ColorfulTheme, Select};
+
+    // Check if there are unstaged changes
+    let status = repo.get_status()?;
+    if status.unstaged_files.is_empty() {
+        return Ok(());
+    }
+
+    if auto_stage {
+        // `commit.auto_stage`: stage everything without asking
+        return repo.stage_all();
+    }
+
+    println!("\n{}", "Unstaged changes detected:".yellow());
+    println!("{}", "─".repeat(50));
+
+    // Show unstaged files
+    for path in &status.unstaged_files {
+        if status.deleted_files.contains(path) {
+            println!("  {} {}", "D".red(), path);
+        } else if status.new_files.contains(path) {
+            println!("  {} {}", "?".red(), path);
         } else {
-            error.trim()
-        };
+            println!("  {} {}", "M".yellow(), path);
+        }
+    }
+    println!("{}", "─".repeat(50));
+
+    let choices = [
+        "Stage all changes (git add -A)",
+        "Choose files and hunks to stage",
+        "Continue with currently staged changes",
+    ];
+    let choice = Select::with_theme(&ColorfulTheme::default())
+        .with_prompt("What would you like to stage?")
+        .items(&choices)
+        .default(0)
+        .interact()?;
+
+    match choice {
+        0 => {
+            repo.stage_all()?;
+            CommitUI::show_info("All changes staged successfully");
+        }
+        1 => stage_interactively(repo)?,
+        _ => CommitUI::show_info("Proceeding with only currently staged changes"),
+    }
+
+    Ok(())
+}
 
-        anyhow::bail!("Git commit failed: {}", error_msg);
+/// Stage the files and hunks the user picks, so the AI sees exactly that subset.
+fn stage_interactively(repo: &GitRepo) -> Result<()> {
+    let files = repo.get_unstaged_files(None)?;
+    let selection = CommitUI::select_changes(&files)?;
+    if selection.is_empty() {
+        CommitUI::show_info("Nothing selected, proceeding with only currently staged changes");
+        return Ok(());
     }
 
+    for pick in &selection {
+        match &pick.hunks {
+            Some(hunks) => repo.stage_hunks(&pick.file.path, hunks)?,
+            None => repo.stage_file(&pick.file.path)?,
+        }
+    }
+    CommitUI::show_info(&format!("Staged changes in {} file(s)", selection.len()));
     Ok(())
 }
@@ -0,0 +1,248 @@
+//! Commit scopes from the repository layout.
+//!
+//! `commit.scope_map` maps path globs to scopes. Without it, scopes are
+//! detected from Cargo workspace members, npm workspaces or the top-level
+//! directories, in that order. Top-level directories are a weak signal, so
+//! they only suggest a scope and never replace the model's choice.
+
+use crate::config::CommitConfig;
+use crate::git::GitRepo;
+use anyhow::{Context, Result};
+use globset::{Glob, GlobBuilder, GlobMatcher};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// Path globs and the scope of the files they match, most specific first.
+#[derive(Default)]
+pub struct ScopeMap {
+    rules: Vec<(String, GlobMatcher, String)>,
+    /// Whether the message's scope must be one of these (false for scopes
+    /// guessed from top-level directories)
+    pub enforced: bool,
+}
+
+impl ScopeMap {
+    /// The configured scope map, or scopes detected from the repository.
+    pub fn load(repo: &GitRepo, config: &CommitConfig) -> Result<Self> {
+        if !config.scope_map.is_empty() {
+            return Self::from_patterns(&config.scope_map);
+        }
+        match repo.workdir() {
+            Some(root) if config.detect_scopes => Ok(Self::detect(root, &repo.tracked_files()?)),
+            _ => Ok(Self::default()),
+        }
+    }
+
+    /// Longer patterns are more specific and take precedence.
+    pub fn from_patterns(patterns: &BTreeMap<String, String>) -> Result<Self> {
+        let mut rules = Vec::new();
+        for (pattern, scope) in patterns {
+            let matcher = Glob::new(pattern)
+                .with_context(|| format!("Invalid scope_map pattern: {}", pattern))?
+                .compile_matcher();
+            rules.push((pattern.clone(), matcher, scope.clone()));
+        }
+        rules.sort_by(|a, b| b.0.len().cmp(&a.0.len()).then_with(|| a.0.cmp(&b.0)));
+        Ok(Self {
+            rules,
+            enforced: true,
+        })
+    }
+
+    /// Detect scopes of the tracked `paths` below `root`.
+    pub fn detect(root: &Path, paths: &[String]) -> Self {
+        let read = |file: &str| std::fs::read_to_string(root.join(file)).ok();
+
+        let cargo_members = read("Cargo.toml")
+            .and_then(|content| content.parse::<toml::Table>().ok())
+            .and_then(|manifest| {
+                let members = manifest.get("workspace")?.get("members")?.as_array()?;
+                Some(strings(members.iter().filter_map(|m| m.as_str())))
+            })
+            .unwrap_or_default();
+        let npm_workspaces = read("package.json")
+            .and_then(|content| serde_json::from_str::<serde_json::Value>(&content).ok())
+            .and_then(|package| {
+                let workspaces = package.get("workspaces")?;
+                // Either an array or `{"packages": [...]}` (yarn)
+                let list = workspaces
+                    .as_array()
+                    .or_else(|| workspaces.get("packages")?.as_array())?;
+                Some(strings(list.iter().filter_map(|w| w.as_str())))
+            })
+            .unwrap_or_default();
+
+        let workspace = [
+            workspace_dirs(&cargo_members, "Cargo.toml", paths),
+            workspace_dirs(&npm_workspaces, "package.json", paths),
+        ]
+        .into_iter()
+        .find(|dirs| !dirs.is_empty());
+        let enforced = workspace.is_some();
+        let dirs = workspace.unwrap_or_else(|| top_level_dirs(paths));
+
+        let patterns = dirs
+            .into_iter()
+            .map(|dir| {
+                let scope = dir.rsplit('/').next().unwrap_or(&dir).to_string();
+                (format!("{}/**", dir), scope)
+            })
+            .collect();
+        Self {
+            enforced,
+            ..Self::from_patterns(&patterns).unwrap_or_default()
+        }
+    }
+
+    /// Scope of the file at `path`.
+    pub fn scope_for(&self, path: &str) -> Option<&str> {
+        self.rules
+            .iter()
+            .find(|(_, matcher, _)| matcher.is_match(path))
+            .map(|(_, _, scope)| scope.as_str())
+    }
+
+    /// Every scope, sorted.
+    pub fn known(&self) -> Vec<String> {
+        let mut scopes: Vec<String> = self.rules.iter().map(|(_, _, s)| s.clone()).collect();
+        scopes.sort();
+        scopes.dedup();
+        scopes
+    }
+
+    /// The scope of every one of `paths`, if they all share one. A path
+    /// outside every scope means there is none.
+    pub fn common<'a>(&self, paths: impl IntoIterator<Item = &'a str>) -> Option<String> {
+        let mut scopes = paths.into_iter().map(|p| self.scope_for(p));
+        let first = scopes.next()??;
+        scopes
+            .all(|scope| scope == Some(first))
+            .then(|| first.to_string())
+    }
+
+    /// Scopes of the changed `paths`, the one with most files first. Paths
+    /// outside every scope are left out.
+    pub fn changed<'a>(&self, paths: impl IntoIterator<Item = &'a str>) -> Vec<String> {
+        let mut counts: BTreeMap<&str, usize> = BTreeMap::new();
+        for scope in paths.into_iter().filter_map(|p| self.scope_for(p)) {
+            *counts.entry(scope).or_default() += 1;
+        }
+        let mut scopes: Vec<(&str, usize)> = counts.into_iter().collect();
+        scopes.sort_by_key(|&(_, count)| std::cmp::Reverse(count));
+        scopes.into_iter().map(|(s, _)| s.to_string()).collect()
+    }
+}
+
+fn strings<'a>(items: impl Iterator<Item = &'a str>) -> Vec<String> {
+    items.map(str::to_string).collect()
+}
+
+/// Directories of workspace members: those matching one of the `members`
+/// globs that contain a `manifest` file.
+fn workspace_dirs(members: &[String], manifest: &str, paths: &[String]) -> Vec<String> {
+    let matchers: Vec<GlobMatcher> = members
+        .iter()
+        .filter_map(|m| {
+            GlobBuilder::new(m.trim_start_matches("./").trim_end_matches('/'))
+                .literal_separator(true)
+                .build()
+                .ok()
+        })
+        .map(|g| g.compile_matcher())
+        .collect();
+
+    let mut dirs: Vec<String> = paths
+        .iter()
+        .filter_map(|p| p.strip_suffix(manifest)?.strip_suffix('/'))
+        .filter(|dir| matchers.iter().any(|m| m.is_match(dir)))
+        .map(str::to_string)
+        .collect();
+    dirs.sort();
+    dirs.dedup();
+    dirs
+}
+
+/// Non-hidden top-level directories. A single one (e.g. only `src/`) says
+/// nothing about the scope, so at least two are required.
+fn top_level_dirs(paths: &[String]) -> Vec<String> {
+    let mut dirs: Vec<String> = paths
+        .iter()
+        .filter_map(|p| p.split_once('/').map(|(dir, _)| dir))
+        .filter(|dir| !dir.starts_with('.'))
+        .map(str::to_string)
+        .collect();
+    dirs.sort();
+    dirs.dedup();
+    if dirs.len() < 2 {
+        dirs.clear();
+    }
+    dirs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn paths(list: &[&str]) -> Vec<String> {
+        list.iter().map(|p| p.to_string()).collect()
+    }
+
+    #[test]
+    fn test_detect_workspaces_and_top_level_dirs() {
+        let dir = tempfile::tempdir().unwrap();
+        let tracked = paths(&[
+            "Cargo.toml",
+            "crates/core/Cargo.toml",
+            "crates/core/src/lib.rs",
+            "crates/cli/Cargo.toml",
+            "crates/cli/src/main.rs",
+            "tools/Cargo.toml",
+            "docs/guide.md",
+        ]);
+
+        std::fs::write(
+            dir.path().join("Cargo.toml"),
+            "[workspace]\nmembers = [\"crates/*\"]\n",
+        )
+        .unwrap();
+        let scopes = ScopeMap::detect(dir.path(), &tracked);
+        assert_eq!(scopes.known(), vec!["cli", "core"]);
+        assert!(scopes.enforced);
+        assert_eq!(
+            scopes.changed([
+                "crates/core/src/lib.rs",
+                "crates/cli/src/main.rs",
+                "crates/core/Cargo.toml",
+                "README.md"
+            ]),
+            vec!["core", "cli"]
+        );
+        assert_eq!(
+            scopes.common(["crates/core/src/lib.rs", "crates/core/Cargo.toml"]),
+            Some("core".to_string())
+        );
+        assert_eq!(scopes.common(["crates/core/src/lib.rs", "README.md"]), None);
+
+        std::fs::write(dir.path().join("Cargo.toml"), "[package]\nname = \"x\"\n").unwrap();
+        let scopes = ScopeMap::detect(dir.path(), &tracked);
+        assert_eq!(scopes.known(), vec!["crates", "docs", "tools"]);
+        assert!(!scopes.enforced);
+        assert!(
+            ScopeMap::detect(dir.path(), &paths(&["src/main.rs", ".github/ci.yml"]))
+                .known()
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn test_longest_pattern_wins() {
+        let patterns = BTreeMap::from([
+            ("src/**".to_string(), "core".to_string()),
+            ("src/ai/**".to_string(), "ai".to_string()),
+        ]);
+        let scopes = ScopeMap::from_patterns(&patterns).unwrap();
+        assert_eq!(scopes.scope_for("src/ai/openai.rs"), Some("ai"));
+        assert_eq!(scopes.scope_for("src/main.rs"), Some("core"));
+        assert_eq!(scopes.scope_for("README.md"), None);
+    }
+}
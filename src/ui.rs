@@ -1,26 +1,39 @@
-use crate::ai::CommitMessage;
+use crate::ai::split::CommitGroup;
+use crate::git::FileDiff;
+use crate::lint::{Report, Violation};
 use anyhow::Result;
 use colored::*;
-use dialoguer::{theme::ColorfulTheme, Confirm, Editor, Select};
+use dialoguer::{theme::ColorfulTheme, Confirm, Editor, Input, MultiSelect, Select};
 
 pub struct CommitUI;
 
 impl CommitUI {
-    pub fn confirm_commit(message: &CommitMessage) -> Result<CommitAction> {
+    /// `violations` are the commit rules the message breaks. `candidates` is
+    /// the number of messages generated so far; with more than one, a
+    /// previous candidate can be picked again.
+    pub fn confirm_commit(
+        message: &str,
+        violations: &[Violation],
+        candidates: usize,
+    ) -> Result<CommitAction> {
         println!("\n{}", "Generated Commit Message:".bold().green());
         println!("{}", "─".repeat(50));
 
         // Display formatted message
-        println!("{}", message.format_conventional().cyan());
+        println!("{}", message.cyan());
         println!("{}", "─".repeat(50));
+        Self::show_violations(violations);
 
         // Show options
-        let options = vec![
+        let mut options = vec![
             "Accept and commit",
             "Edit message",
-            "Regenerate with different AI",
-            "Cancel",
+            "Regenerate (with a hint, another model or provider)",
         ];
+        if candidates > 1 {
+            options.push("Choose a previous candidate");
+        }
+        options.push("Cancel");
 
         let selection = Select::with_theme(&ColorfulTheme::default())
             .with_prompt("What would you like to do?")
@@ -28,10 +41,10 @@ impl CommitUI {
             .default(0)
             .interact()?;
 
-        match selection {
-            0 => Ok(CommitAction::Accept),
-            1 => {
-                let edited = Editor::new().edit(&message.format_conventional())?;
+        match options[selection] {
+            "Accept and commit" => Ok(CommitAction::Accept),
+            "Edit message" => {
+                let edited = Editor::new().edit(message)?;
 
                 match edited {
                     Some(content) if !content.trim().is_empty() => Ok(CommitAction::Edit(content)),
@@ -42,11 +55,210 @@ impl CommitUI {
                     None => Ok(CommitAction::Cancel),
                 }
             }
-            2 => Ok(CommitAction::Regenerate),
-            _ => Ok(CommitAction::Cancel),
+            "Choose a previous candidate" => Ok(CommitAction::PickCandidate),
+            "Cancel" => Ok(CommitAction::Cancel),
+            _ => Ok(CommitAction::Regenerate),
         }
     }
 
+    /// Ask how to regenerate. `providers` are the configured providers,
+    /// `current` the one in use.
+    pub fn ask_regeneration(
+        model: &str,
+        providers: &[String],
+        current: usize,
+    ) -> Result<Regeneration> {
+        let theme = ColorfulTheme::default();
+        let mut options = vec!["Regenerate", "Regenerate with a hint", "Switch model"];
+        if providers.len() > 1 {
+            options.push("Switch provider");
+        }
+
+        let selection = Select::with_theme(&theme)
+            .with_prompt("How should the message be regenerated?")
+            .items(&options)
+            .default(1)
+            .interact()?;
+
+        match selection {
+            1 => {
+                let hint: String = Input::with_theme(&theme)
+                    .with_prompt("Hint (e.g. \"shorter\", \"mention the migration\")")
+                    .allow_empty(true)
+                    .interact_text()?;
+                match hint.trim() {
+                    "" => Ok(Regeneration::Again),
+                    hint => Ok(Regeneration::Hint(hint.to_string())),
+                }
+            }
+            2 => {
+                let name: String = Input::with_theme(&theme)
+                    .with_prompt("Model")
+                    .default(model.to_string())
+                    .interact_text()?;
+                Ok(Regeneration::Model(name.trim().to_string()))
+            }
+            3 => {
+                let index = Select::with_theme(&theme)
+                    .with_prompt("Provider")
+                    .items(providers)
+                    .default(current)
+                    .interact()?;
+                Ok(Regeneration::Provider(index))
+            }
+            _ => Ok(Regeneration::Again),
+        }
+    }
+
+    /// Show every `(label, message)` candidate and let the user pick one,
+    /// optionally after comparing two of them line by line.
+    pub fn select_candidate(candidates: &[(String, String)], default: usize) -> Result<usize> {
+        let theme = ColorfulTheme::default();
+
+        println!("\n{}", "Candidates:".bold().green());
+        for (i, (label, message)) in candidates.iter().enumerate() {
+            println!("{}", "─".repeat(50));
+            println!("{} {}", format!("#{}", i + 1).bold(), label.dimmed());
+            println!("{}", message.trim_end().cyan());
+        }
+        println!("{}", "─".repeat(50));
+
+        let mut items: Vec<String> = candidates
+            .iter()
+            .enumerate()
+            .map(|(i, (_, message))| {
+                format!("#{} {}", i + 1, message.lines().next().unwrap_or_default())
+            })
+            .collect();
+        items.push("Compare two candidates".to_string());
+
+        loop {
+            let selection = Select::with_theme(&theme)
+                .with_prompt("Which message should be used?")
+                .items(&items)
+                .default(default)
+                .interact()?;
+            if selection < candidates.len() {
+                return Ok(selection);
+            }
+
+            let picked = MultiSelect::with_theme(&theme)
+                .with_prompt("Select two candidates to compare")
+                .items(&items[..candidates.len()])
+                .interact()?;
+            let [a, b] = picked[..] else {
+                println!("{}", "Select exactly two candidates.".yellow());
+                continue;
+            };
+
+            println!(
+                "\n{}",
+                format!("Changes from #{} to #{}:", a + 1, b + 1).bold()
+            );
+            println!("{}", "─".repeat(50));
+            for (tag, line) in diff_lines(&candidates[a].1, &candidates[b].1) {
+                match tag {
+                    '-' => println!("{}", format!("- {}", line).red()),
+                    '+' => println!("{}", format!("+ {}", line).green()),
+                    _ => println!("  {}", line),
+                }
+            }
+            println!("{}", "─".repeat(50));
+        }
+    }
+
+    /// Show two messages next to each other, e.g. HEAD's current message and
+    /// the generated one.
+    pub fn show_comparison(title: &str, left: (&str, &str), right: (&str, &str)) {
+        const WIDTH: usize = 38;
+
+        let left_lines = wrap_lines(left.1.trim_end(), WIDTH);
+        let right_lines = wrap_lines(right.1.trim_end(), WIDTH);
+
+        println!("\n{}", title.bold().green());
+        println!("{}", "─".repeat(WIDTH * 2 + 3));
+        println!("{} │ {}", pad(left.0, WIDTH).bold(), right.0.bold());
+        println!("{}", "─".repeat(WIDTH * 2 + 3));
+        for i in 0..left_lines.len().max(right_lines.len()) {
+            let l = left_lines.get(i).map(String::as_str).unwrap_or_default();
+            let r = right_lines.get(i).map(String::as_str).unwrap_or_default();
+            println!("{} │ {}", pad(l, WIDTH).dimmed(), r.cyan());
+        }
+        println!("{}", "─".repeat(WIDTH * 2 + 3));
+    }
+
+    /// List the commit rules a message breaks.
+    pub fn show_violations(violations: &[Violation]) {
+        if violations.is_empty() {
+            return;
+        }
+        println!("{}", "Commit rule violations:".yellow().bold());
+        for violation in violations {
+            println!(
+                "  {} {} {}",
+                "✗".yellow(),
+                violation.message,
+                format!("[{}]", violation.rule).dimmed()
+            );
+        }
+    }
+
+    /// Result of `lint`: the messages that break rules out of `checked`.
+    pub fn show_lint_reports(failed: &[Report], checked: usize) {
+        for report in failed {
+            println!(
+                "{} {} {}",
+                "✗".red().bold(),
+                report.source.yellow(),
+                report.summary
+            );
+            for violation in &report.violations {
+                println!(
+                    "    {} {}",
+                    violation.message,
+                    format!("[{}]", violation.rule).dimmed()
+                );
+            }
+        }
+
+        if failed.is_empty() {
+            Self::show_success(&format!(
+                "{} commit message(s) follow the commit rules",
+                checked
+            ));
+        } else {
+            println!(
+                "\n{}",
+                format!(
+                    "{} of {} commit message(s) break the commit rules",
+                    failed.len(),
+                    checked
+                )
+                .red()
+                .bold()
+            );
+        }
+    }
+
+    pub fn show_hook_rejection(hook: &str, output: &str) {
+        println!(
+            "\n{} {}",
+            "✗".red().bold(),
+            format!("The {} hook rejected the commit message:", hook).red()
+        );
+        if !output.is_empty() {
+            println!("{}", output);
+        }
+        println!("{}", "Edit the message and try again, or cancel.".yellow());
+    }
+
+    pub fn show_hook_rewrite(message: &str) {
+        println!("\n{}", "Commit message was changed by a git hook:".yellow());
+        println!("{}", "─".repeat(50));
+        println!("{}", message.trim_end().cyan());
+        println!("{}", "─".repeat(50));
+    }
+
     pub fn show_diff_preview(diff: &str, max_lines: usize) -> Result<bool> {
         let lines: Vec<&str> = diff.lines().collect();
         let total_lines = lines.len();
@@ -55,16 +267,7 @@ impl CommitUI {
         println!("{}", "─".repeat(50));
 
         for line in lines.iter().take(max_lines) {
-            let colored_line = if line.starts_with('+') && !line.starts_with("+++") {
-                line.green()
-            } else if line.starts_with('-') && !line.starts_with("---") {
-                line.red()
-            } else if line.starts_with("@@") {
-                line.cyan()
-            } else {
-                line.normal()
-            };
-            println!("{}", colored_line);
+            println!("{}", colorize_diff_line(line));
         }
 
         if total_lines > max_lines {
@@ -80,6 +283,193 @@ impl CommitUI {
             .map_err(Into::into)
     }
 
+    /// Pick files to stage, then hunks of the picked files that have more than one.
+    pub fn select_changes(files: &[FileDiff]) -> Result<Vec<StagePick<'_>>> {
+        let items: Vec<String> = files
+            .iter()
+            .map(|file| {
+                let kind = if file.header.contains("\nnew file mode") {
+                    "A".green()
+                } else if file.header.contains("\ndeleted file mode") {
+                    "D".red()
+                } else {
+                    "M".yellow()
+                };
+                let detail = if file.hunks.is_empty() {
+                    "binary".to_string()
+                } else {
+                    format!(
+                        "+{} -{}, {} hunk(s)",
+                        file.added,
+                        file.removed,
+                        file.hunks.len()
+                    )
+                };
+                format!("{} {} ({})", kind, file.path, detail)
+            })
+            .collect();
+
+        let picked = MultiSelect::with_theme(&ColorfulTheme::default())
+            .with_prompt("Select files to stage (Space to select, Enter to confirm)")
+            .items(&items)
+            .interact()?;
+
+        let mut selection = Vec::new();
+        for file in picked.into_iter().map(|i| &files[i]) {
+            if file.hunks.len() <= 1 {
+                selection.push(StagePick { file, hunks: None });
+                continue;
+            }
+
+            println!("\n{}", file.path.bold());
+            for (i, hunk) in file.hunks.iter().enumerate() {
+                println!("{}", format!("[{}]", i + 1).bold());
+                let lines: Vec<&str> = hunk.lines().collect();
+                for line in lines.iter().take(20) {
+                    println!("{}", colorize_diff_line(line));
+                }
+                if lines.len() > 20 {
+                    println!("... {} more lines ...", lines.len() - 20);
+                }
+            }
+
+            let hunk_items: Vec<String> = file
+                .hunks
+                .iter()
+                .enumerate()
+                .map(|(i, hunk)| {
+                    let added = hunk.lines().filter(|l| l.starts_with('+')).count();
+                    let removed = hunk.lines().filter(|l| l.starts_with('-')).count();
+                    let header = hunk.lines().next().unwrap_or_default();
+                    format!("[{}] {} (+{} -{})", i + 1, header, added, removed)
+                })
+                .collect();
+            let defaults = vec![true; hunk_items.len()];
+            let hunks = MultiSelect::with_theme(&ColorfulTheme::default())
+                .with_prompt(format!("Select hunks of {} to stage", file.path))
+                .items(&hunk_items)
+                .defaults(&defaults)
+                .interact()?;
+
+            if hunks.len() == file.hunks.len() {
+                selection.push(StagePick { file, hunks: None });
+            } else if !hunks.is_empty() {
+                selection.push(StagePick {
+                    file,
+                    hunks: Some(hunks),
+                });
+            }
+        }
+
+        Ok(selection)
+    }
+
+    pub fn show_commit_groups(groups: &[CommitGroup], files: &[FileDiff]) {
+        println!("\n{}", "Proposed Commits:".bold().green());
+        println!("{}", "─".repeat(50));
+        for (i, group) in groups.iter().enumerate() {
+            let subject = group.message.lines().next().unwrap_or_default();
+            if group.is_unassigned() {
+                println!(
+                    "{} {}",
+                    format!("{}.", i + 1).bold(),
+                    "Unassigned changes (edit a message or merge into another commit)".yellow()
+                );
+            } else {
+                println!("{} {}", format!("{}.", i + 1).bold(), subject.cyan());
+            }
+            for file in group.files() {
+                let diff = &files[file];
+                let hunks = group
+                    .changes
+                    .iter()
+                    .filter(|c| c.file == file && c.hunk.is_some())
+                    .count();
+                if hunks > 0 && hunks < diff.hunks.len() {
+                    println!("   {} ({} of {} hunks)", diff.path, hunks, diff.hunks.len());
+                } else {
+                    println!("   {}", diff.path);
+                }
+            }
+        }
+        println!("{}", "─".repeat(50));
+    }
+
+    pub fn select_split_action(groups: &[CommitGroup]) -> Result<SplitAction> {
+        let theme = ColorfulTheme::default();
+        let subjects: Vec<String> = groups
+            .iter()
+            .enumerate()
+            .map(|(i, g)| {
+                let subject = match g.is_unassigned() {
+                    true => "Unassigned changes",
+                    false => g.message.lines().next().unwrap_or_default(),
+                };
+                format!("{}. {}", i + 1, subject)
+            })
+            .collect();
+
+        let options = vec![
+            "Create these commits",
+            "Edit a message",
+            "Move a commit",
+            "Merge commits",
+            "Cancel",
+        ];
+        let selection = Select::with_theme(&theme)
+            .with_prompt("What would you like to do?")
+            .items(&options)
+            .default(0)
+            .interact()?;
+
+        match selection {
+            0 => Ok(SplitAction::CommitAll),
+            1 => {
+                let index = Select::with_theme(&theme)
+                    .with_prompt("Which commit message?")
+                    .items(&subjects)
+                    .default(0)
+                    .interact()?;
+                match Editor::new().edit(&groups[index].message)? {
+                    Some(content) if !content.trim().is_empty() => {
+                        Ok(SplitAction::Edit(index, content))
+                    }
+                    Some(_) => {
+                        println!("{}", "Commit message cannot be empty!".red());
+                        Ok(SplitAction::Review)
+                    }
+                    None => Ok(SplitAction::Review),
+                }
+            }
+            2 => {
+                let from = Select::with_theme(&theme)
+                    .with_prompt("Which commit?")
+                    .items(&subjects)
+                    .default(0)
+                    .interact()?;
+                let positions: Vec<String> = (1..=groups.len()).map(|p| p.to_string()).collect();
+                let to = Select::with_theme(&theme)
+                    .with_prompt("Move to position")
+                    .items(&positions)
+                    .default(from)
+                    .interact()?;
+                Ok(SplitAction::Move { from, to })
+            }
+            3 => {
+                let picked = MultiSelect::with_theme(&theme)
+                    .with_prompt("Select commits to merge (Space to select, Enter to confirm)")
+                    .items(&subjects)
+                    .interact()?;
+                if picked.len() < 2 {
+                    println!("{}", "Select at least two commits to merge.".yellow());
+                    return Ok(SplitAction::Review);
+                }
+                Ok(SplitAction::Merge(picked))
+            }
+            _ => Ok(SplitAction::Cancel),
+        }
+    }
+
     pub fn get_api_key(provider: &str) -> Result<String> {
         use dialoguer::Password;
 
@@ -105,9 +495,151 @@ impl CommitUI {
     }
 }
 
+/// Line diff of `old` to `new` from their longest common subsequence:
+/// `-` lines only in `old`, `+` lines only in `new`, ` ` lines in both.
+fn diff_lines<'a>(old: &'a str, new: &'a str) -> Vec<(char, &'a str)> {
+    let a: Vec<&str> = old.trim_end().lines().collect();
+    let b: Vec<&str> = new.trim_end().lines().collect();
+
+    // lcs[i][j] = common lines of a[i..] and b[j..]
+    let mut lcs = vec![vec![0usize; b.len() + 1]; a.len() + 1];
+    for i in (0..a.len()).rev() {
+        for j in (0..b.len()).rev() {
+            lcs[i][j] = if a[i] == b[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let (mut i, mut j) = (0, 0);
+    let mut lines = Vec::new();
+    while i < a.len() || j < b.len() {
+        if i < a.len() && j < b.len() && a[i] == b[j] {
+            lines.push((' ', a[i]));
+            i += 1;
+            j += 1;
+        } else if j < b.len() && (i == a.len() || lcs[i][j + 1] >= lcs[i + 1][j]) {
+            lines.push(('+', b[j]));
+            j += 1;
+        } else {
+            lines.push(('-', a[i]));
+            i += 1;
+        }
+    }
+    lines
+}
+
+/// Terminal columns taken by `c`; CJK and emoji are two columns wide.
+fn char_width(c: char) -> usize {
+    match c as u32 {
+        0x1100..=0x115F
+        | 0x2E80..=0xA4CF
+        | 0xAC00..=0xD7A3
+        | 0xF900..=0xFAFF
+        | 0xFE30..=0xFE4F
+        | 0xFF00..=0xFF60
+        | 0xFFE0..=0xFFE6
+        | 0x1F300..=0x1F64F
+        | 0x1F900..=0x1F9FF => 2,
+        _ => 1,
+    }
+}
+
+/// Break every line of `text` so that none is wider than `width` columns.
+fn wrap_lines(text: &str, width: usize) -> Vec<String> {
+    let mut lines = Vec::new();
+    for line in text.lines() {
+        let mut current = String::new();
+        let mut used = 0;
+        for c in line.chars() {
+            let w = char_width(c);
+            if used + w > width {
+                lines.push(std::mem::take(&mut current));
+                used = 0;
+            }
+            current.push(c);
+            used += w;
+        }
+        lines.push(current);
+    }
+    lines
+}
+
+fn pad(text: &str, width: usize) -> String {
+    let used: usize = text.chars().map(char_width).sum();
+    format!("{}{}", text, " ".repeat(width.saturating_sub(used)))
+}
+
+fn colorize_diff_line(line: &str) -> ColoredString {
+    if line.starts_with('+') && !line.starts_with("+++") {
+        line.green()
+    } else if line.starts_with('-') && !line.starts_with("---") {
+        line.red()
+    } else if line.starts_with("@@") {
+        line.cyan()
+    } else {
+        line.normal()
+    }
+}
+
+/// A file chosen in `select_changes`.
+pub struct StagePick<'a> {
+    pub file: &'a FileDiff,
+    /// Positions of the hunks to stage, `None` for the whole file
+    pub hunks: Option<Vec<usize>>,
+}
+
+pub enum SplitAction {
+    CommitAll,
+    Edit(usize, String),
+    Move {
+        from: usize,
+        to: usize,
+    },
+    /// Merge into the first selected commit, keeping its message
+    Merge(Vec<usize>),
+    /// Nothing changed, show the commits again
+    Review,
+    Cancel,
+}
+
 pub enum CommitAction {
     Accept,
     Edit(String),
     Regenerate,
+    PickCandidate,
     Cancel,
 }
+
+/// What changes for the next generation.
+pub enum Regeneration {
+    Again,
+    Hint(String),
+    Model(String),
+    /// Index into the configured providers (`Config::providers`)
+    Provider(usize),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_diff_lines() {
+        assert_eq!(diff_lines("a\nb\n", "a\nb"), vec![(' ', "a"), (' ', "b")]);
+        assert_eq!(
+            diff_lines("a\nb", "x\ny"),
+            vec![('+', "x"), ('+', "y"), ('-', "a"), ('-', "b")]
+        );
+        assert_eq!(
+            diff_lines("a\nc", "a\nb\nc"),
+            vec![(' ', "a"), ('+', "b"), (' ', "c")]
+        );
+        assert_eq!(
+            diff_lines("a\nb\nc", "c\na\nb"),
+            vec![('+', "c"), (' ', "a"), (' ', "b"), ('-', "c")]
+        );
+    }
+}